@@ -0,0 +1,248 @@
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+use tauri::InvokeError;
+use crate::handle::{ONE_METERS_TO_LATITUDE, ONE_METERS_TO_LONGITUDE, plane_distance};
+use crate::photo::{Photo, scan_photos};
+use crate::station::{Station, STATION};
+use crate::station::edit::{edit_station, LedgerEdit};
+use crate::station::order::ordered_stations;
+use crate::utils::{new_invoke_err, to_invoke_err};
+
+/// 最近一次检测到的照片簇, 供新增/修正杆塔时使用
+pub static CLUSTERS: Lazy<Mutex<Vec<PhotoCluster>>> = Lazy::new(|| {
+    Mutex::new(vec![])
+});
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ClusterOptions {
+    /// 邻域半径(米)
+    pub eps: f64,
+    /// 成簇的最少照片数
+    pub min_points: usize,
+    /// 簇中心与杆塔偏差超过该值(米)视为坐标有误
+    pub offset_threshold: f64,
+    /// 簇中心附近该距离(米)内没有杆塔视为台账缺塔
+    pub missing_distance: f64,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        ClusterOptions {
+            eps: 15.0,
+            min_points: 3,
+            offset_threshold: 10.0,
+            missing_distance: 50.0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ClusterKind {
+    /// 与台账杆塔吻合
+    #[default]
+    Matched,
+    /// 台账杆塔坐标偏差较大
+    Offset,
+    /// 附近没有台账杆塔
+    Missing,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoCluster {
+    pub index: usize,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub photo_count: usize,
+    /// 最近杆塔的 Station::key()
+    pub nearest_station: Option<String>,
+    pub distance: Option<f64>,
+    pub kind: ClusterKind,
+}
+
+/// 对照片位置做密度聚类, 并与台账杆塔比较
+#[tauri::command]
+pub async fn detect_clusters(photo_path: &str, options: Option<ClusterOptions>) -> Result<String, InvokeError> {
+    let options = options.unwrap_or_default();
+
+    let photos: Vec<Photo> = scan_photos(photo_path).await.map_err(to_invoke_err)?.photos.into_keys().collect();
+    let stations = ordered_stations().await;
+
+    let clusters = find_clusters(&stations, &photos, &options);
+    *CLUSTERS.lock().await = clusters.clone();
+
+    let json = serde_json::to_string(&clusters).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 按照片簇新增缺失的杆塔(需要 name)或将偏差杆塔移到簇中心, 与手动修改台账相同, 可以撤销, 返回修改后的线路树
+#[tauri::command]
+pub async fn apply_cluster(index: usize, name: Option<String>) -> Result<String, InvokeError> {
+    let cluster = CLUSTERS.lock().await.iter().find(|v| v.index == index).cloned()
+        .ok_or(new_invoke_err("cluster not found"))?;
+
+    let stations = STATION.lock().await.clone();
+    let nearest = stations.iter().find(|v| Some(v.key()) == cluster.nearest_station);
+    let line = nearest.map(|v| v.line.clone()).unwrap_or_default();
+
+    let edit = match cluster.kind {
+        ClusterKind::Missing => {
+            // 新增的杆塔归入最近杆塔所在的线路
+            let name = name.ok_or(new_invoke_err("station name is null"))?;
+            let station = Station {
+                name,
+                longitude: cluster.longitude,
+                latitude: cluster.latitude,
+                ..Default::default()
+            };
+            let line_stations: Vec<Station> = stations.iter().filter(|v| v.line == line).cloned().collect();
+            let index = Some(insert_position(&line_stations, &station));
+            LedgerEdit::Add { station, index }
+        }
+        ClusterKind::Offset => LedgerEdit::Move {
+            name: nearest.ok_or(new_invoke_err("station not found"))?.name.clone(),
+            longitude: cluster.longitude,
+            latitude: cluster.latitude,
+            height: None,
+        },
+        ClusterKind::Matched => return Err(new_invoke_err("cluster already matches a station")),
+    };
+
+    edit_station(edit, Some(line)).await
+}
+
+pub fn find_clusters(stations: &[Station], photos: &[Photo], options: &ClusterOptions) -> Vec<PhotoCluster> {
+    let mut clusters = vec![];
+
+    for (index, members) in dbscan(photos, options.eps, options.min_points).into_iter().enumerate() {
+        let count = members.len() as f64;
+        let longitude = members.iter().map(|v| photos[*v].longitude).sum::<f64>() / count;
+        let latitude = members.iter().map(|v| photos[*v].latitude).sum::<f64>() / count;
+
+        let nearest = stations.iter()
+            .map(|v| (v, plane_distance(v.longitude, v.latitude, longitude, latitude)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        let kind = match nearest {
+            Some((_, distance)) if distance <= options.offset_threshold => ClusterKind::Matched,
+            Some((_, distance)) if distance <= options.missing_distance => ClusterKind::Offset,
+            _ => ClusterKind::Missing,
+        };
+
+        clusters.push(PhotoCluster {
+            index,
+            longitude,
+            latitude,
+            photo_count: members.len(),
+            nearest_station: nearest.map(|v| v.0.key()),
+            distance: nearest.map(|v| v.1),
+            kind,
+        });
+    }
+
+    clusters
+}
+
+/// DBSCAN 聚类, 返回每个簇中照片的下标, 噪声点不输出
+fn dbscan(photos: &[Photo], eps: f64, min_points: usize) -> Vec<Vec<usize>> {
+    let neighbours = |idx: usize| -> Vec<usize> {
+        (0..photos.len())
+            .filter(|v| {
+                let dx = (photos[*v].longitude - photos[idx].longitude) / ONE_METERS_TO_LONGITUDE;
+                let dy = (photos[*v].latitude - photos[idx].latitude) / ONE_METERS_TO_LATITUDE;
+                (dx * dx + dy * dy).sqrt() <= eps
+            })
+            .collect()
+    };
+
+    let mut visited = vec![false; photos.len()];
+    let mut assigned = vec![false; photos.len()];
+    let mut clusters = vec![];
+
+    for idx in 0..photos.len() {
+        if visited[idx] {
+            continue;
+        }
+        visited[idx] = true;
+
+        let mut queue = neighbours(idx);
+        if queue.len() < min_points {
+            continue;
+        }
+
+        let mut members = vec![];
+        while let Some(v) = queue.pop() {
+            if !assigned[v] {
+                assigned[v] = true;
+                members.push(v);
+            }
+            if visited[v] {
+                continue;
+            }
+            visited[v] = true;
+
+            let next = neighbours(v);
+            if next.len() >= min_points {
+                queue.extend(next);
+            }
+        }
+        clusters.push(members);
+    }
+
+    clusters
+}
+
+/// 新增杆塔插入到使线路总长增加最少的位置
+fn insert_position(stations: &[Station], station: &Station) -> usize {
+    if stations.is_empty() {
+        return 0;
+    }
+
+    let mut best = (0, station.distance_to(&stations[0]));
+
+    let last = station.distance_to(&stations[stations.len() - 1]);
+    if last < best.1 {
+        best = (stations.len(), last);
+    }
+
+    for (idx, v) in stations.windows(2).enumerate() {
+        if v[0].line != v[1].line {
+            continue;
+        }
+        let added = v[0].distance_to(station) + station.distance_to(&v[1]) - v[0].distance_to(&v[1]);
+        if added < best.1 {
+            best = (idx + 1, added);
+        }
+    }
+
+    best.0
+}
+
+#[test]
+fn test_find_clusters() {
+    use crate::fixtures::{photo_at, station};
+
+    // 1 号塔坐标准确, 2 号塔偏差约 26 米, 1、2 号之间缺一基塔
+    let stations = vec![station("1", 120.0), station("2", 120.004)];
+    let mut photos = vec![];
+    for (longitude, latitude) in [(120.0, 30.0), (120.0020, 30.0), (120.00425, 30.0)] {
+        for idx in 0..4 {
+            photos.push(photo_at(longitude + idx as f64 * 0.00003, latitude + idx as f64 * 0.00002));
+        }
+    }
+    // 孤立的噪声点
+    photos.push(photo_at(120.01, 30.01));
+
+    let clusters = find_clusters(&stations, &photos, &ClusterOptions::default());
+    assert_eq!(clusters.len(), 3);
+
+    let kinds: Vec<ClusterKind> = clusters.iter().map(|v| v.kind).collect();
+    assert_eq!(kinds, vec![ClusterKind::Matched, ClusterKind::Missing, ClusterKind::Offset]);
+    assert_eq!(clusters[2].nearest_station.as_deref(), Some("2"));
+
+    let missing = Station { longitude: clusters[1].longitude, latitude: clusters[1].latitude, ..Default::default() };
+    assert_eq!(insert_position(&stations, &missing), 1);
+}
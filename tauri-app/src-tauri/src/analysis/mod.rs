@@ -0,0 +1,3 @@
+pub mod radius;
+pub mod cluster;
+pub mod thermal;
@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use crate::handle::distance_meters;
+use crate::photo::{Photo, scan_photos};
+use crate::station::{span_lengths, Station};
+use crate::station::order::ordered_stations;
+use crate::utils::to_invoke_err;
+
+/// 直方图默认分组宽度(米)
+pub static DEFAULT_BIN_WIDTH: f64 = 5.0;
+
+/// 直方图最多分组数, 超出部分并入最后一组
+pub static MAX_BINS: usize = 100;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoDistance {
+    pub file_name: String,
+    pub nearest_station: String,
+    pub nearest_distance: f64,
+    pub second_station: Option<String>,
+    pub second_distance: Option<f64>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct HistogramBin {
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct RadiusSuggestion {
+    /// 照片到最近杆塔距离的分布
+    pub histogram: Vec<HistogramBin>,
+    /// 最小档距(米)
+    pub min_span: Option<f64>,
+    /// 按距离分布中最大间隙得到的半径
+    pub gap_radius: Option<f64>,
+    pub suggested: Option<f64>,
+    pub warnings: Vec<String>,
+    pub photos: Vec<PhotoDistance>,
+}
+
+/// 分析照片到杆塔的距离分布并给出建议半径, 传入 radius 时检查其是否过大
+#[tauri::command]
+pub async fn suggest_radius(photo_path: &str, radius: Option<&str>, bin_width: Option<f64>) -> Result<String, InvokeError> {
+    let radius: Option<f64> = match radius {
+        Some(v) => Some(v.parse().map_err(|e: std::num::ParseFloatError|anyhow!(e)).map_err(to_invoke_err)?),
+        None => None,
+    };
+
+    let photos: Vec<Photo> = scan_photos(photo_path).await.map_err(to_invoke_err)?.photos.into_keys().collect();
+    let stations = ordered_stations().await;
+
+    let suggestion = analyse_radius(&stations, &photos, radius, bin_width.unwrap_or(DEFAULT_BIN_WIDTH));
+
+    let json = serde_json::to_string(&suggestion).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+pub fn analyse_radius(stations: &[Station], photos: &[Photo], radius: Option<f64>, bin_width: f64) -> RadiusSuggestion {
+    let mut suggestion = RadiusSuggestion::default();
+
+    for photo in photos.iter() {
+        let mut distances: Vec<(&Station, f64)> = stations.iter().map(|v| (v, distance_meters(v, photo))).collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((station, distance)) = distances.first() {
+            suggestion.photos.push(PhotoDistance {
+                file_name: photo.file_name.clone(),
+                nearest_station: station.key(),
+                nearest_distance: *distance,
+                second_station: distances.get(1).map(|v| v.0.key()),
+                second_distance: distances.get(1).map(|v| v.1),
+            });
+        }
+    }
+    suggestion.photos.sort_by(|a, b| a.nearest_distance.total_cmp(&b.nearest_distance));
+
+    let nearest: Vec<f64> = suggestion.photos.iter().map(|v| v.nearest_distance).collect();
+    suggestion.histogram = histogram(&nearest, bin_width);
+
+    let spans = span_lengths(stations);
+    suggestion.min_span = spans.iter().map(|v| v.1).filter(|v| *v > 0.0).min_by(f64::total_cmp);
+    let half_span = suggestion.min_span.map(|v| v / 2.0);
+
+    suggestion.gap_radius = gap_radius(&nearest);
+    suggestion.suggested = match (suggestion.gap_radius, half_span) {
+        (Some(gap), Some(half)) => Some(gap.min(half)),
+        (Some(gap), None) => Some(gap),
+        (None, Some(half)) => Some(half),
+        (None, None) => None,
+    };
+
+    if let Some(radius) = radius {
+        if let Some(half) = half_span {
+            if radius > half {
+                suggestion.warnings.push(format!("半径 {:.1} 米超过最小档距的一半 {:.1} 米, 照片可能同时归属到相邻杆塔", radius, half));
+            }
+        }
+
+        for (idx, span) in spans.iter().cloned() {
+            if radius * 2.0 > span {
+                suggestion.warnings.push(format!("{} - {} 档距 {:.1} 米, 小于半径的两倍", stations[idx].name, stations[idx + 1].name, span));
+            }
+        }
+    }
+
+    suggestion
+}
+
+fn histogram(values: &[f64], bin_width: f64) -> Vec<HistogramBin> {
+    let max = match values.iter().cloned().max_by(f64::total_cmp) {
+        Some(max) if bin_width > 0.0 => max,
+        _ => return vec![],
+    };
+
+    let bins = ((max / bin_width).floor() as usize + 1).min(MAX_BINS);
+    let mut histogram: Vec<HistogramBin> = (0..bins)
+        .map(|i| HistogramBin { from: i as f64 * bin_width, to: (i + 1) as f64 * bin_width, count: 0 })
+        .collect();
+
+    for value in values.iter() {
+        let idx = ((value / bin_width).floor() as usize).min(bins - 1);
+        histogram[idx].count += 1;
+    }
+    if let Some(last) = histogram.last_mut() {
+        last.to = last.to.max(max);
+    }
+
+    histogram
+}
+
+/// 已排序的距离中, 至少一半照片落在其内侧的最大间隙的中点
+fn gap_radius(sorted: &[f64]) -> Option<f64> {
+    if sorted.len() < 2 {
+        return sorted.first().cloned();
+    }
+
+    let start = (sorted.len() - 1) / 2;
+    (start..sorted.len() - 1)
+        .max_by(|a, b| (sorted[a + 1] - sorted[*a]).total_cmp(&(sorted[b + 1] - sorted[*b])))
+        .map(|i| (sorted[i] + sorted[i + 1]) / 2.0)
+}
+
+#[test]
+fn test_analyse_radius() {
+    use crate::fixtures::{photo, station};
+
+    // 档距约 438 米, 照片集中在塔附近 10~20 米, 另有一张离塔约 130 米
+    let stations = vec![station("1", 120.0), station("2", 120.005)];
+    let photos = vec![
+        photo("a", 120.0001), photo("b", 120.0002), photo("c", 120.00015),
+        photo("d", 120.0049), photo("e", 120.0048), photo("f", 120.0015),
+    ];
+
+    let suggestion = analyse_radius(&stations, &photos, Some(300.0), 5.0);
+
+    assert!((suggestion.min_span.unwrap() - 438.2).abs() < 0.5);
+    let suggested = suggestion.suggested.unwrap();
+    assert!(suggested > 20.0 && suggested < 130.0);
+    assert_eq!(suggestion.histogram.iter().map(|v| v.count).sum::<usize>(), 6);
+    assert_eq!(suggestion.warnings.len(), 2);
+}
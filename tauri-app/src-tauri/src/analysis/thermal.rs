@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use tokio::sync::Mutex;
+use crate::handle::BELONG_MAP;
+use crate::photo::{Photo, PhotoError, PhotoType, PHOTOS};
+use crate::photo::thermal::{read_thermal, ThermalParams, ThermalSdk, ThermalStats};
+use crate::station::TreeNode;
+use crate::station::order::ordered_stations;
+use crate::utils::to_invoke_err;
+
+/// 红外照片的测温结果, 以照片路径为键
+pub static THERMAL: Lazy<Mutex<HashMap<String, PhotoThermal>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// 最近一次测温使用的参数
+pub static THERMAL_OPTIONS: Lazy<Mutex<ThermalOptions>> = Lazy::new(|| {
+    Mutex::new(ThermalOptions::default())
+});
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ThermalOptions {
+    pub params: ThermalParams,
+    /// 测温 SDK 动态库路径, 不指定时在程序目录中查找
+    pub sdk_path: Option<String>,
+    /// 环境温度(℃), 用于计算温升
+    pub ambient: f64,
+    /// 最高温度超过该值(℃)视为超温
+    pub max_temperature: f64,
+    /// 最高温度高于全图平均温度该值(K)视为局部过热
+    pub max_rise: f64,
+}
+
+impl Default for ThermalOptions {
+    fn default() -> Self {
+        ThermalOptions {
+            params: ThermalParams::default(),
+            sdk_path: None,
+            ambient: 25.0,
+            max_temperature: 80.0,
+            max_rise: 20.0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoThermal {
+    pub path: String,
+    pub file_name: String,
+    pub stats: ThermalStats,
+    /// 实际使用的测温参数
+    pub params: ThermalParams,
+    /// 超过阈值的原因, 未超过时为 None
+    pub alarm: Option<String>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct TowerThermal {
+    pub station: String,
+    pub max: Option<f64>,
+    pub photos: Vec<PhotoThermal>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct ThermalResult {
+    pub towers: Vec<TowerThermal>,
+    /// 测温失败的红外照片
+    pub failed: Vec<PhotoError>,
+}
+
+/// 解析已加载的红外照片的温度数据, 按杆塔返回测温结果和测温失败的照片
+#[tauri::command]
+pub async fn analyse_thermal(options: Option<ThermalOptions>) -> Result<String, InvokeError> {
+    let options = options.unwrap_or_default();
+    *THERMAL_OPTIONS.lock().await = options.clone();
+
+    let (thermal, failed) = analyse_loaded_photos(options).await.map_err(to_invoke_err)?;
+
+    let belong_map = BELONG_MAP.lock().await.clone();
+    let towers: Vec<TowerThermal> = ordered_stations().await.iter().filter_map(|station| {
+        let mut photos: Vec<PhotoThermal> = belong_map.get(station)?.keys()
+            .filter_map(|v| thermal.get(&v.path).cloned())
+            .collect();
+        if photos.is_empty() {
+            return None;
+        }
+        photos.sort_by(|a, b| b.stats.max.total_cmp(&a.stats.max));
+
+        Some(TowerThermal {
+            station: station.key(),
+            max: photos.first().map(|v| v.stats.max),
+            photos,
+        })
+    }).collect();
+
+    let json = serde_json::to_string(&ThermalResult { towers, failed }).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 解析已加载的全部红外照片, 结果保存到 THERMAL, 返回测温结果和测温失败的照片
+pub async fn analyse_loaded_photos(options: ThermalOptions) -> anyhow::Result<(HashMap<String, PhotoThermal>, Vec<PhotoError>)> {
+    let photos: Vec<Photo> = PHOTOS.lock().await.keys().filter(|v| v.photo_type == PhotoType::Infrared).cloned().collect();
+    let (thermal, failed) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        if photos.is_empty() {
+            return Ok((HashMap::new(), vec![]));
+        }
+        let sdk = ThermalSdk::load(options.sdk_path.as_deref())?;
+        Ok(analyse_photos(&photos, &options, &sdk))
+    }).await.map_err(|e|anyhow!(e))??;
+    *THERMAL.lock().await = thermal.clone();
+
+    Ok((thermal, failed))
+}
+
+/// 不是辐射 JPEG 的照片不输出, 解析失败的照片单独返回
+pub fn analyse_photos(photos: &[Photo], options: &ThermalOptions, sdk: &ThermalSdk) -> (HashMap<String, PhotoThermal>, Vec<PhotoError>) {
+    let mut thermal = HashMap::new();
+    let mut failed = vec![];
+
+    for photo in photos.iter() {
+        let (image, params) = match read_thermal(photo.path.as_str(), &options.params, sdk) {
+            Ok(Some(v)) => v,
+            Ok(None) => continue,
+            Err(e) => {
+                failed.push(PhotoError::new(&photo.path, e));
+                continue;
+            }
+        };
+        let stats = image.stats();
+
+        thermal.insert(photo.path.clone(), PhotoThermal {
+            path: photo.path.clone(),
+            file_name: photo.file_name.clone(),
+            alarm: judge_alarm(&stats, options),
+            stats,
+            params,
+        });
+    }
+
+    (thermal, failed)
+}
+
+pub fn judge_alarm(stats: &ThermalStats, options: &ThermalOptions) -> Option<String> {
+    if stats.max > options.max_temperature {
+        Some(format!("最高温度 {:.1}℃ 超过 {:.1}℃", stats.max, options.max_temperature))
+    } else if stats.max - stats.mean > options.max_rise {
+        Some(format!("最高温度高于平均温度 {:.1}K", stats.max - stats.mean))
+    } else {
+        None
+    }
+}
+
+/// 杆塔下超温照片的树节点, 没有超温照片时返回 None
+pub fn thermal_node<'a>(photos: impl Iterator<Item = &'a Photo>, thermal: &HashMap<String, PhotoThermal>) -> Option<TreeNode> {
+    let mut alarms: Vec<&PhotoThermal> = photos.filter_map(|v| thermal.get(&v.path)).filter(|v| v.alarm.is_some()).collect();
+    if alarms.is_empty() {
+        return None;
+    }
+    alarms.sort_by(|a, b| b.stats.max.total_cmp(&a.stats.max));
+
+    let children = alarms.iter().map(|v| TreeNode {
+        key: v.path.clone(),
+        label: format!("{}: {}", v.file_name, v.alarm.as_deref().unwrap_or_default()),
+        children: None,
+    }).collect();
+
+    Some(TreeNode {
+        key: "thermal".to_string(),
+        label: format!("超温: {}", alarms.len()),
+        children: Some(children),
+    })
+}
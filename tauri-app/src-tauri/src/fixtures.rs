@@ -0,0 +1,42 @@
+//! 单元测试共用的杆塔和照片数据
+use crate::photo::Photo;
+use crate::station::Station;
+
+/// 测试杆塔沿该纬线由西向东排列, 经度相差 0.001 约 96 米
+pub static LATITUDE: f64 = 30.0;
+
+/// 位于 LATITUDE 纬线上的杆塔
+pub fn station(name: &str, longitude: f64) -> Station {
+    station_at(name, longitude, LATITUDE)
+}
+
+pub fn station_at(name: &str, longitude: f64, latitude: f64) -> Station {
+    Station { name: name.to_string(), longitude, latitude, ..Default::default() }
+}
+
+/// 位于 LATITUDE 纬线上的照片, 文件名同时作为路径
+///
+/// 照片按类型和坐标区分(见 Photo 的 Hash), 同一测试中的照片需要使用不同的坐标
+pub fn photo(name: &str, longitude: f64) -> Photo {
+    Photo {
+        longitude,
+        latitude: LATITUDE,
+        path: name.to_string(),
+        file_name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+/// 只有位置的照片
+pub fn photo_at(longitude: f64, latitude: f64) -> Photo {
+    Photo { longitude, latitude, ..Default::default() }
+}
+
+/// 带拍摄时间的照片, 相机时间与本地时间相同
+pub fn timed_photo(name: &str, longitude: f64, time: &str) -> Photo {
+    Photo {
+        capture_time: Some(time.to_string()),
+        local_time: Some(time.to_string()),
+        ..photo(name, longitude)
+    }
+}
@@ -0,0 +1,63 @@
+use crate::handle::AssignOptions;
+use crate::station::Station;
+
+/// 为每基杆塔确定归属半径, stations 需按线路顺序排列
+///
+/// 优先级: 台账中的半径 > 按塔型指定的半径 > 自适应半径 > 全局半径
+pub fn station_radii(radius: f64, stations: &[Station], options: &AssignOptions) -> Vec<(Station, f64)> {
+    stations.iter().enumerate().map(|(idx, station)| {
+        if let Some(radius) = station.radius {
+            return (station.clone(), radius);
+        }
+
+        if let Some(radius) = station.tower_type.as_ref().and_then(|v| options.type_radius.get(v)) {
+            return (station.clone(), *radius);
+        }
+
+        if !options.adaptive_radius {
+            return (station.clone(), radius);
+        }
+
+        let prev = idx.checked_sub(1).and_then(|i| stations.get(i)).filter(|v| v.line == station.line).map(|v| station.distance_to(v));
+        let next = stations.get(idx + 1).filter(|v| v.line == station.line).map(|v| station.distance_to(v));
+        let span = [prev, next].into_iter().flatten().filter(|v| *v > 0.0).min_by(f64::total_cmp);
+
+        let mut adaptive = span.map_or(radius, |v| v * options.span_ratio);
+        if let Some(min) = options.min_radius {
+            adaptive = adaptive.max(min);
+        }
+        if let Some(max) = options.max_radius {
+            adaptive = adaptive.min(max);
+        }
+
+        (station.clone(), adaptive)
+    }).collect()
+}
+
+#[test]
+fn test_station_radii() {
+    use std::collections::HashMap;
+    use crate::fixtures::station;
+
+    // 档距约 44 米和 438 米
+    let mut stations = vec![station("1", 120.0), station("2", 120.0005), station("3", 120.0055), station("4", 120.0105)];
+    stations[3].radius = Some(15.0);
+    stations[2].tower_type = Some("耐张塔".to_string());
+
+    let options = AssignOptions {
+        adaptive_radius: true,
+        max_radius: Some(100.0),
+        type_radius: HashMap::from([("耐张塔".to_string(), 80.0)]),
+        ..Default::default()
+    };
+
+    let radii: Vec<f64> = station_radii(50.0, &stations, &options).into_iter().map(|v| v.1).collect();
+
+    assert!((radii[0] - 17.5).abs() < 0.1);
+    assert!((radii[1] - 17.5).abs() < 0.1);
+    assert_eq!(radii[2], 80.0);
+    assert_eq!(radii[3], 15.0);
+
+    let radii: Vec<f64> = station_radii(50.0, &stations[..2], &AssignOptions::default()).into_iter().map(|v| v.1).collect();
+    assert_eq!(radii, vec![50.0, 50.0]);
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use crate::handle::{AssignMode, AssignOptions, Belong, ONE_METERS_TO_LATITUDE, ONE_METERS_TO_LONGITUDE};
+use crate::handle::elevation::{assign_distance, relative_height};
+use crate::photo::Photo;
+use crate::station::Station;
+
+/// 视线与相机到杆塔方向之间的夹角(度), 没有云台偏航角时返回 None
+///
+/// 有俯仰角且能算出相对高度时按三维方向计算(目标为塔身中部), 否则只比较水平方向
+pub fn angular_error(station: &Station, photo: &Photo, options: &AssignOptions) -> Option<f64> {
+    let yaw = photo.gimbal_yaw?.to_radians();
+
+    let east = (station.longitude - photo.longitude) / ONE_METERS_TO_LONGITUDE;
+    let north = (station.latitude - photo.latitude) / ONE_METERS_TO_LATITUDE;
+
+    let (view, target) = match (photo.gimbal_pitch, relative_height(station, photo)) {
+        (Some(pitch), Some(relative)) => {
+            let pitch = pitch.to_radians();
+            let up = options.tower_height / 2.0 - relative;
+            ([yaw.sin() * pitch.cos(), yaw.cos() * pitch.cos(), pitch.sin()], [east, north, up])
+        }
+        _ => ([yaw.sin(), yaw.cos(), 0.0], [east, north, 0.0]),
+    };
+
+    let norm = (target[0] * target[0] + target[1] * target[1] + target[2] * target[2]).sqrt();
+    if norm == 0.0 {
+        return Some(0.0);
+    }
+
+    let cos = (view[0] * target[0] + view[1] * target[1] + view[2] * target[2]) / norm;
+
+    Some(cos.clamp(-1.0, 1.0).acos().to_degrees())
+}
+
+/// 朝向模式: 每张照片只归属到一基杆塔, 半径范围内优先选视场内最接近视线的杆塔, 否则取最近的杆塔
+pub fn assign_by_bearing(stations: &[(Station, f64)], photos: &[Photo], options: &AssignOptions) -> HashMap<Station, HashMap<Photo, Belong>> {
+    let mut belong_map: HashMap<Station, HashMap<Photo, Belong>> = HashMap::new();
+    let half_fov = options.field_of_view / 2.0;
+
+    for photo in photos.iter() {
+        let candidates: Vec<(&Station, f64, Option<f64>)> = stations.iter()
+            .filter_map(|(station, radius)| {
+                let distance = assign_distance(station, photo, options).filter(|v| v <= radius)?;
+                Some((station, distance, angular_error(station, photo, options)))
+            })
+            .collect();
+
+        let in_view = candidates.iter()
+            .filter(|v| v.2.is_some_and(|err| err <= half_fov))
+            .min_by(|a, b| a.2.unwrap().total_cmp(&b.2.unwrap()));
+
+        let (station, belong) = match in_view {
+            Some((station, distance, err)) => (*station, Belong {
+                distance: *distance,
+                method: AssignMode::Bearing,
+                stop: None,
+                angular_error: *err,
+            }),
+            None => match candidates.iter().min_by(|a, b| a.1.total_cmp(&b.1)) {
+                Some((station, distance, err)) => (*station, Belong {
+                    distance: *distance,
+                    method: AssignMode::Radius,
+                    stop: None,
+                    angular_error: *err,
+                }),
+                None => continue,
+            },
+        };
+
+        belong_map.entry(station.clone()).or_default().insert(photo.clone(), belong);
+    }
+
+    belong_map
+}
+
+#[test]
+fn test_assign_by_bearing() {
+    use crate::fixtures;
+
+    let station = |name: &str, longitude: f64| (fixtures::station(name, longitude), 60.0);
+    let photo = |name: &str, latitude: f64, yaw: Option<f64>| Photo {
+        latitude,
+        gimbal_yaw: yaw,
+        gimbal_pitch: Some(-20.0),
+        ..fixtures::photo(name, 120.0002)
+    };
+
+    // 无人机在两塔之间, 离 1 号塔约 18 米, 离 2 号塔约 26 米
+    let stations = vec![station("1", 120.0), station("2", 120.0005)];
+    let photos = vec![photo("east", 30.0, Some(92.0)), photo("west", 30.00001, Some(-88.0)), photo("none", 30.00002, None)];
+    let options = AssignOptions { mode: AssignMode::Bearing, ..Default::default() };
+
+    let belong_map = assign_by_bearing(&stations, &photos, &options);
+
+    let east = &belong_map[&stations[1].0][&photos[0]];
+    assert_eq!(east.method, AssignMode::Bearing);
+    assert!((east.angular_error.unwrap() - 2.0).abs() < 0.1);
+
+    let west = &belong_map[&stations[0].0][&photos[1]];
+    assert_eq!(west.method, AssignMode::Bearing);
+
+    let none = &belong_map[&stations[0].0][&photos[2]];
+    assert_eq!(none.method, AssignMode::Radius);
+    assert_eq!(none.angular_error, None);
+}
@@ -0,0 +1,127 @@
+use crate::handle::{AssignOptions, distance_meters};
+use crate::photo::Photo;
+use crate::station::{Station, TreeNode};
+
+/// 杆塔高程是否可用, KML 中贴地的点高度为 0, 视为没有高程
+fn has_elevation(station: &Station) -> bool {
+    station.height != 0.0
+}
+
+/// 照片相对塔基的高度(米), 杆塔没有高程时使用相对起飞点的高度, 起飞点一般在塔基附近
+pub fn relative_height(station: &Station, photo: &Photo) -> Option<f64> {
+    match photo.altitude {
+        Some(altitude) if has_elevation(station) => Some(altitude - station.height),
+        _ => photo.relative_altitude,
+    }
+}
+
+/// 相机位置到杆塔(塔基到塔顶的竖直线段)的三维斜距
+pub fn slant_distance(station: &Station, photo: &Photo, tower_height: f64) -> Option<f64> {
+    let relative = relative_height(station, photo)?;
+    let horizontal = distance_meters(station, photo);
+
+    let vertical = if relative < 0.0 {
+        -relative
+    } else if relative > tower_height {
+        relative - tower_height
+    } else {
+        0.0
+    };
+
+    Some((horizontal * horizontal + vertical * vertical).sqrt())
+}
+
+/// 照片高度是否在允许范围内, 缺少高度信息时不过滤
+pub fn height_allowed(station: &Station, photo: &Photo, options: &AssignOptions) -> bool {
+    let relative = match relative_height(station, photo) {
+        Some(relative) => relative,
+        None => return true,
+    };
+
+    !options.min_relative_height.is_some_and(|min| relative < min)
+        && !options.max_relative_height.is_some_and(|max| relative > max)
+}
+
+/// 设置了高度范围或三维距离时, 因缺少高度未按高度处理的照片, 没有这类照片时返回 None
+pub fn height_skipped_node(stations: &[Station], photos: &[Photo], options: &AssignOptions) -> Option<TreeNode> {
+    if !options.use_3d && options.min_relative_height.is_none() && options.max_relative_height.is_none() {
+        return None;
+    }
+
+    let mut skipped: Vec<&Photo> = photos.iter()
+        .filter(|photo| stations.iter().any(|station| relative_height(station, photo).is_none()))
+        .collect();
+    if skipped.is_empty() {
+        return None;
+    }
+    skipped.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    let children = skipped.iter().map(|v| TreeNode {
+        key: v.path.clone(),
+        label: v.file_name.clone(),
+        children: None,
+    }).collect();
+
+    Some(TreeNode {
+        key: "height-skipped".to_string(),
+        label: format!("缺少高度, 未按高度过滤: {}", skipped.len()),
+        children: Some(children),
+    })
+}
+
+/// 归属时使用的距离, 高度不符合时返回 None; 开启三维但缺少高度信息时退回平面距离
+pub fn assign_distance(station: &Station, photo: &Photo, options: &AssignOptions) -> Option<f64> {
+    if !height_allowed(station, photo, options) {
+        return None;
+    }
+
+    if options.use_3d {
+        if let Some(distance) = slant_distance(station, photo, options.tower_height) {
+            return Some(distance);
+        }
+    }
+
+    Some(distance_meters(station, photo))
+}
+
+#[test]
+fn test_assign_distance() {
+    use crate::fixtures::{photo_at, station};
+
+    let station = Station { height: 100.0, ..station("1", 120.0) };
+    let photo = |altitude: Option<f64>| Photo { altitude, ..photo_at(120.00034, 30.0) };
+
+    let options = AssignOptions {
+        use_3d: true,
+        tower_height: 40.0,
+        min_relative_height: Some(5.0),
+        ..Default::default()
+    };
+
+    // 水平约 29.8 米, 高于塔顶 40 米
+    let distance = assign_distance(&station, &photo(Some(180.0)), &options).unwrap();
+    assert!((distance - 49.9).abs() < 0.1);
+
+    // 塔身高度范围内只计水平距离
+    let distance = assign_distance(&station, &photo(Some(120.0)), &options).unwrap();
+    assert!((distance - 29.8).abs() < 0.1);
+
+    // 起飞时贴近地面拍摄
+    assert_eq!(assign_distance(&station, &photo(Some(101.0)), &options), None);
+
+    // 没有高度信息时退回平面距离
+    let distance = assign_distance(&station, &photo(None), &options).unwrap();
+    assert!((distance - 29.8).abs() < 0.1);
+
+    // 台账没有高程时按相对起飞点的高度过滤
+    let flat = Station { height: 0.0, ..station.clone() };
+    let relative = |relative_altitude: Option<f64>| Photo { relative_altitude, altitude: Some(180.0), ..photo_at(120.00034, 30.0) };
+    assert_eq!(assign_distance(&flat, &relative(Some(1.0)), &options), None);
+    let distance = assign_distance(&flat, &relative(Some(60.0)), &options).unwrap();
+    assert!((distance - 35.9).abs() < 0.1);
+
+    let photos = vec![relative(Some(60.0)), photo(None)];
+    let node = height_skipped_node(&[flat], &photos, &options).unwrap();
+    assert_eq!(node.children.unwrap().len(), 1);
+    assert!(height_skipped_node(&[station], &photos, &AssignOptions::default()).is_none());
+}
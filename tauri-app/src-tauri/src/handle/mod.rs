@@ -1,27 +1,194 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use anyhow::anyhow;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tauri::InvokeError;
 use tokio::fs::File;
 use tokio::sync::Mutex;
-use crate::photo::{Photo, photo_list, PhotoType};
-use crate::station::{STATION, Station, TreeNode};
+use crate::photo::{failed_node, Photo, photo_list, PhotoError, FAILED, PHOTOS, PhotoType};
+use crate::photo::annotate::{tag_photo, PhotoTag};
+use crate::photo::privacy::{write_sidecar, PrivacyOptions};
+use crate::photo::overlay::{overlay_photos, OverlayJob, OverlayOptions};
+use crate::photo::thumbnail::THUMBNAIL_ROOTS;
+use crate::station::{path_segment, STATION, Station, TreeNode};
 use crate::station::kml::kml_to_json;
+use crate::station::line::multi_line;
+use crate::station::order::ordered_stations;
+use crate::handle::trajectory::{assign_by_stops, Stop};
+use crate::handle::adaptive::station_radii;
+use crate::handle::elevation::{assign_distance, height_skipped_node};
+use crate::handle::bearing::assign_by_bearing;
+use crate::handle::span::{assign_by_span, Span, span_tree};
+use crate::handle::video::{extract_keyframes, keyframes, write_segments, VIDEO_SEGMENTS};
 use crate::utils::{ensure_dir_exists, new_invoke_err, to_invoke_err};
+use crate::analysis::thermal::{thermal_node, THERMAL};
+use crate::analysis::cluster::CLUSTERS;
 
-pub static BELONG_MAP: Lazy<Mutex<HashMap<Station, HashMap<Photo, bool>>>> = Lazy::new(|| {
+pub mod trajectory;
+pub mod adaptive;
+pub mod elevation;
+pub mod bearing;
+pub mod span;
+pub mod video;
+
+pub static BELONG_MAP: Lazy<Mutex<HashMap<Station, HashMap<Photo, Belong>>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// 通道巡检模式下照片按档的归属结果
+pub static SPAN_MAP: Lazy<Mutex<HashMap<Span, HashMap<Photo, Belong>>>> = Lazy::new(|| {
     Mutex::new(HashMap::new())
 });
 
+/// 最近一次归属使用的半径和选项, 修改台账后据此重新归属
+pub static LAST_ASSIGN: Lazy<Mutex<Option<(f64, AssignOptions)>>> = Lazy::new(|| {
+    Mutex::new(None)
+});
+
+/// 最近一次归属时每基杆塔使用的半径(米)
+pub static STATION_RADIUS: Lazy<Mutex<HashMap<Station, f64>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// 轨迹模式下识别出的停留点
+pub static STOPS: Lazy<Mutex<Vec<Stop>>> = Lazy::new(|| {
+    Mutex::new(vec![])
+});
+
+/// 最近一次导出照片的目录
+pub static OUTPUT_PATH: Lazy<Mutex<String>> = Lazy::new(|| {Mutex::new(String::new())});
+
+/// 最近一次输出使用的杆塔目录模板
+pub static OUTPUT_TEMPLATE: Lazy<Mutex<Option<String>>> = Lazy::new(|| {Mutex::new(None)});
+
 /// 1米 = 0.00001141经度
 pub static ONE_METERS_TO_LONGITUDE: f64 = 0.00001141;
 
 /// 1米 = 0.00000899纬度
 pub static ONE_METERS_TO_LATITUDE: f64 = 0.00000899;
 
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum AssignMode {
+    /// 按半径归属
+    #[default]
+    Radius,
+    /// 按拍摄时间分段, 每个停留点整体归属到最近的杆塔
+    Trajectory,
+    /// 按云台朝向归属到视场内最接近视线的杆塔
+    Bearing,
+    /// 通道巡检, 照片归属到相邻两塔之间的档, 半径为通道中心线两侧的宽度
+    Span,
+}
+
+impl AssignMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssignMode::Radius => "半径",
+            AssignMode::Trajectory => "轨迹",
+            AssignMode::Bearing => "朝向",
+            AssignMode::Span => "档",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AssignOptions {
+    pub mode: AssignMode,
+    /// 相邻照片拍摄间隔超过该秒数时分为新的停留点
+    pub stop_time_gap: f64,
+    /// 相邻照片位置相距超过该距离(米)时分为新的停留点
+    pub stop_move_distance: f64,
+    /// 停留点中心到杆塔的距离不超过 半径*该系数 时才归属
+    pub stop_radius_factor: f64,
+    /// 按相邻档距为每基杆塔计算半径
+    pub adaptive_radius: bool,
+    /// 自适应半径 = 较短的相邻档距 * 该比例
+    pub span_ratio: f64,
+    pub min_radius: Option<f64>,
+    pub max_radius: Option<f64>,
+    /// 按塔型指定的半径, 优先于自适应半径
+    pub type_radius: HashMap<String, f64>,
+    /// 使用照片海拔与杆塔高程计算三维斜距
+    pub use_3d: bool,
+    /// 塔顶相对塔基的高度(米)
+    pub tower_height: f64,
+    /// 照片相对塔基的高度低于该值时不参与归属, 用于排除起降照片
+    pub min_relative_height: Option<f64>,
+    /// 照片相对塔基的高度高于该值时不参与归属
+    pub max_relative_height: Option<f64>,
+    /// 朝向模式下相机视场角(度), 杆塔偏离视线不超过其一半时视为在视场内
+    pub field_of_view: f64,
+    /// 只归属到这些线路的杆塔, 为空时归属到全部已加载的线路
+    pub lines: Vec<String>,
+    /// 只归属到属性符合的杆塔, 如 {"voltage": "220"}
+    pub station_filter: HashMap<String, String>,
+}
+
+impl Default for AssignOptions {
+    fn default() -> Self {
+        AssignOptions {
+            mode: AssignMode::default(),
+            stop_time_gap: 30.0,
+            stop_move_distance: 30.0,
+            stop_radius_factor: 2.0,
+            adaptive_radius: false,
+            span_ratio: 0.4,
+            min_radius: None,
+            max_radius: None,
+            type_radius: HashMap::new(),
+            use_3d: false,
+            tower_height: 0.0,
+            min_relative_height: None,
+            max_relative_height: None,
+            field_of_view: 60.0,
+            lines: vec![],
+            station_filter: HashMap::new(),
+        }
+    }
+}
+
+/// 输出照片时的选项
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OutputOptions {
+    /// 杆塔目录模板, 如 "{voltage}/{name}", 不指定时为杆塔编号
+    pub template: Option<String>,
+    /// 本机有 ffmpeg 时截取视频片段的关键帧
+    pub keyframes: bool,
+    /// 将线路、杆塔、距离和照片类型写入输出照片的 EXIF 和 XMP
+    pub write_tags: bool,
+    /// 交给外部单位时删除或粗化定位信息, 删除设备序列号, 归属信息另写到目录中的 CSV
+    pub privacy: PrivacyOptions,
+    /// 在照片上叠加线路、杆塔、时间、坐标等文字
+    pub overlay: OverlayOptions,
+}
+
+/// 照片归属到杆塔的依据
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Belong {
+    /// 照片到杆塔的距离(米)
+    pub distance: f64,
+    pub method: AssignMode,
+    /// 所属停留点序号
+    pub stop: Option<usize>,
+    /// 视线与杆塔方向的夹角(度)
+    pub angular_error: Option<f64>,
+}
+
+/// 单张照片的归属结果
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoAssignment {
+    pub file_name: String,
+    pub path: String,
+    pub line: String,
+    pub station: String,
+    #[serde(flatten)]
+    pub belong: Belong,
+}
+
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct CalcPhotoResult {
     pub normal: u64,
@@ -45,64 +212,212 @@ impl CalcPhotoResult {
     }
 }
 
-pub async fn judge_photo_belong(radius: &str, photo_path: &str) -> anyhow::Result<()> {
+/// 两个经纬度点之间的平面距离(米)
+pub fn plane_distance(longitude_a: f64, latitude_a: f64, longitude_b: f64, latitude_b: f64) -> f64 {
+    let y = (latitude_a - latitude_b) / ONE_METERS_TO_LATITUDE;
+    let x = (longitude_a - longitude_b) / ONE_METERS_TO_LONGITUDE;
+
+    (x * x + y * y).sqrt()
+}
+
+/// 照片到杆塔的平面距离(米)
+pub fn distance_meters(station: &Station, photo: &Photo) -> f64 {
+    plane_distance(station.longitude, station.latitude, photo.longitude, photo.latitude)
+}
+
+pub async fn judge_photo_belong(radius: &str, photo_path: &str, options: &AssignOptions) -> anyhow::Result<()> {
     
     let radius: f64 = radius.parse().map_err(|e: std::num::ParseFloatError|anyhow!(e))?;
-    let circle = radius * radius;
     
-    let photos = photo_list(photo_path).await?;
+    let photos: Vec<Photo> = photo_list(photo_path).await?.photos.into_keys().collect();
 
-    let stations = STATION.lock().await.clone();
+    *LAST_ASSIGN.lock().await = Some((radius, options.clone()));
 
-    let mut belong_map: HashMap<Station, HashMap<Photo, bool>> = HashMap::new();
+    assign_photos(radius, &photos, options).await
+}
 
-    for station in stations.into_iter() {
-        let station_y = station.latitude / ONE_METERS_TO_LATITUDE;
-        let station_x = station.longitude / ONE_METERS_TO_LONGITUDE;
+/// 按当前台账重新归属照片
+///
+/// 杆塔归属和档归属的结果同时更新, 切换模式后不会残留上一种模式的结果
+async fn assign_photos(radius: f64, photos: &[Photo], options: &AssignOptions) -> anyhow::Result<()> {
+    let stations = assign_stations(options).await;
+
+    let mut station_radius = vec![];
+    let mut stops = vec![];
+    let mut span_map = HashMap::new();
+    let belong_map = match options.mode {
+        AssignMode::Radius => {
+            station_radius = station_radii(radius, &stations, options);
+            assign_by_radius(&station_radius, photos, options)
+        }
+        AssignMode::Trajectory => {
+            station_radius = station_radii(radius, &stations, options);
+            let (belong_map, stop_list) = assign_by_stops(&station_radius, photos, options);
+            stops = stop_list;
+            belong_map
+        }
+        AssignMode::Bearing => {
+            station_radius = station_radii(radius, &stations, options);
+            assign_by_bearing(&station_radius, photos, options)
+        }
+        AssignMode::Span => {
+            span_map = assign_by_span(&stations, photos, radius);
+            HashMap::new()
+        }
+    };
 
-        for (photo, b_) in photos.iter() {
-            let photo_y = photo.latitude / ONE_METERS_TO_LATITUDE;
-            let photo_x = photo.longitude / ONE_METERS_TO_LONGITUDE;
+    *BELONG_MAP.lock().await = belong_map;
+    *SPAN_MAP.lock().await = span_map;
+    *STATION_RADIUS.lock().await = station_radius.into_iter().collect();
+    *STOPS.lock().await = stops;
+
+    Ok(())
+}
+
+/// 参与归属的杆塔, 按线路过滤
+async fn assign_stations(options: &AssignOptions) -> Vec<Station> {
+    let mut stations = ordered_stations().await;
+    if !options.lines.is_empty() {
+        stations.retain(|v| options.lines.contains(&v.line));
+    }
+    stations.retain(|v| options.station_filter.iter().all(|(key, value)| v.attribute(key).as_ref() == Some(value)));
+
+    stations
+}
+
+/// 报告统计的杆塔, 与最近一次归属时使用的杆塔相同, 还没有归属时为全部杆塔
+///
+/// 按档归属时没有杆塔的归属结果, 不能生成杆塔报告
+pub async fn report_stations() -> anyhow::Result<Vec<Station>> {
+    let options = LAST_ASSIGN.lock().await.clone().map(|v| v.1).unwrap_or_default();
+    if options.mode == AssignMode::Span {
+        return Err(anyhow::Error::msg("tower report is not available for span assignment"));
+    }
+
+    Ok(assign_stations(&options).await)
+}
+
+/// 照片输出的子目录, 多条线路时按线路分目录
+pub fn output_folder(line: &str, folder: impl AsRef<Path>, grouped: bool) -> PathBuf {
+    if grouped && !line.is_empty() {
+        Path::new(&path_segment(line)).join(folder)
+    } else {
+        folder.as_ref().to_path_buf()
+    }
+}
 
-            let line = ((photo_x - station_x)*(photo_x - station_x) + (photo_y - station_y)*(photo_y - station_y)).abs();
+/// 杆塔照片的输出子目录, 指定模板时按模板生成, 如 "{voltage}/{name}", 模板生成的目录为空时使用杆塔编号
+pub fn station_folder(station: &Station, template: Option<&str>, grouped: bool) -> PathBuf {
+    let folder = template.map(|v| station.render_path(v))
+        .filter(|v| v.components().next().is_some())
+        .unwrap_or_else(|| PathBuf::from(path_segment(&station.name)));
 
-            // println!("station: {}, line: {}, circle: {}", station.name, line, circle);
-            if line > circle {
+    output_folder(&station.line, folder, grouped)
+}
+
+/// 移除线路的归属结果, line 为 None 时清空全部, 在重新导入或移除台账前调用
+pub async fn clear_line_results(line: Option<&str>) {
+    let removed = |v: &str| line.is_none() || line == Some(v);
+    let keys: Vec<String> = STATION.lock().await.iter().filter(|v| removed(&v.line)).map(|v| v.key()).collect();
+
+    BELONG_MAP.lock().await.retain(|station, _| !removed(&station.line));
+    SPAN_MAP.lock().await.retain(|span, _| !removed(&span.from.line));
+    STATION_RADIUS.lock().await.retain(|station, _| !removed(&station.line));
+    VIDEO_SEGMENTS.lock().await.retain(|station, _| !removed(&station.line));
+    // 停留点是整段飞行的分段, 只取消其到被移除杆塔的归属
+    for stop in STOPS.lock().await.iter_mut().filter(|v| v.station.as_ref().is_some_and(|v| keys.contains(v))) {
+        stop.station = None;
+        stop.distance = None;
+    }
+    // 照片簇与全部台账比较得到, 台账变化后需要重新检测
+    CLUSTERS.lock().await.clear();
+}
+
+/// 台账修改后重新归属, 半径模式下只重算受影响的杆塔, 其他模式照片只归属一处, 需要整体重算
+pub async fn reassign_stations(affected: &[String]) -> anyhow::Result<()> {
+    let (radius, options) = match LAST_ASSIGN.lock().await.clone() {
+        Some(last) => last,
+        None => return Ok(()),
+    };
+    let photos: Vec<Photo> = PHOTOS.lock().await.keys().cloned().collect();
+
+    if options.mode != AssignMode::Radius {
+        return assign_photos(radius, &photos, &options).await;
+    }
+
+    let stations = station_radii(radius, &assign_stations(&options).await, &options);
+    *STATION_RADIUS.lock().await = stations.iter().cloned().collect();
+
+    let mut belong_map = BELONG_MAP.lock().await;
+    // 被删除、移动或改名的杆塔与当前台账中的不再相等, 一并移除
+    belong_map.retain(|station, _| !affected.contains(&station.key()) && stations.iter().any(|v| v.0 == *station));
+
+    let changed: Vec<(Station, f64)> = stations.into_iter().filter(|v| affected.contains(&v.0.key())).collect();
+    belong_map.extend(assign_by_radius(&changed, &photos, &options));
+
+    Ok(())
+}
+
+/// 照片归属到半径范围内的所有杆塔, stations 中为每基杆塔及其半径
+pub fn assign_by_radius(stations: &[(Station, f64)], photos: &[Photo], options: &AssignOptions) -> HashMap<Station, HashMap<Photo, Belong>> {
+    let mut belong_map: HashMap<Station, HashMap<Photo, Belong>> = HashMap::new();
+
+    for (station, radius) in stations.iter() {
+        for photo in photos.iter() {
+            let line = match assign_distance(station, photo, options) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            // println!("station: {}, line: {}, radius: {}", station.name, line, radius);
+            if line > *radius {
                 continue
             } else {
-                let mut photo_map_exist = false;
-                if let Some(map) = belong_map.get_mut(&station) {
-                    map.insert(photo.clone(), true);
-                    photo_map_exist = true;
-                }
-
-                if !photo_map_exist {
-                    let mut photo_map = HashMap::new();
-                    photo_map.insert(photo.clone(), true);
-                    belong_map.insert(station.clone(), photo_map);
-                }
+                belong_map.entry(station.clone()).or_default().insert(photo.clone(), Belong {
+                    distance: line,
+                    method: AssignMode::Radius,
+                    stop: None,
+                    angular_error: None,
+                });
             }
         }
     }
 
-    *BELONG_MAP.lock().await = belong_map;
-    
-    Ok(())
+    belong_map
 }
 
 #[tauri::command]
-pub async fn calc_photo(radius: &str, photo_path: &str) -> Result<String, InvokeError> {
+pub async fn calc_photo(radius: &str, photo_path: &str, options: Option<AssignOptions>) -> Result<String, InvokeError> {
 
-    let _ = judge_photo_belong(radius, photo_path).await.map_err(to_invoke_err)?;
+    let options = options.unwrap_or_default();
+    let _ = judge_photo_belong(radius, photo_path, &options).await.map_err(to_invoke_err)?;
+
+    // 档由参与归属的相邻杆塔组成, 与归属时使用的杆塔一致
+    if options.mode == AssignMode::Span {
+        let span_map = SPAN_MAP.lock().await.clone();
+        let mut tree_node_list = span_tree(&assign_stations(&options).await, &span_map);
+        tree_node_list.extend(failed_node(&FAILED.lock().await));
+        let json = serde_json::to_string(&tree_node_list).map_err(|e|new_invoke_err(e.to_string().as_str()))?;
+
+        return Ok(json);
+    }
 
 
     let map = BELONG_MAP.lock().await.clone();
+    let stops = STOPS.lock().await.clone();
+    let station_radius = STATION_RADIUS.lock().await.clone();
+    let thermal = THERMAL.lock().await.clone();
+    let grouped = multi_line().await;
     let mut total_result = CalcPhotoResult::default();
     let mut tree_node_list = vec![];
 
     let mut station_tree_node_list = vec![];
 
-    for (station, photo_map) in map.iter() {
+    for station in ordered_stations().await.iter() {
+        let photo_map = match map.get(station) {
+            Some(photo_map) => photo_map,
+            None => continue,
+        };
         let mut cr = CalcPhotoResult::default();
         for (photo, _) in photo_map.into_iter() {
             match photo.photo_type {
@@ -118,10 +433,21 @@ pub async fn calc_photo(radius: &str, photo_path: &str) -> Result<String, Invoke
         }
 
         if cr.normal != 0 || cr.infrared != 0 {
+            let mut children = cr.to_tree_node();
+            if let Some(radius) = station_radius.get(station) {
+                children.push(TreeNode{
+                    key: "radius".to_string(),
+                    label: format!("半径: {:.1}米", radius),
+                    children: None,
+                });
+            }
+            children.extend(stops.iter().filter(|v| v.station.as_ref() == Some(&station.key())).map(|v| v.to_tree_node()));
+            children.extend(thermal_node(photo_map.keys(), &thermal));
+
             station_tree_node_list.push(TreeNode{
-                key: station.name.clone(),
-                label: station.name.clone(),
-                children: Some(cr.to_tree_node()),
+                key: station.key(),
+                label: if grouped { station.key() } else { station.name.clone() },
+                children: Some(children),
             });
         }
     }
@@ -131,6 +457,9 @@ pub async fn calc_photo(radius: &str, photo_path: &str) -> Result<String, Invoke
         label: "总数".to_string(),
         children: Some(total_result.to_tree_node()),
     });
+    tree_node_list.extend(failed_node(&FAILED.lock().await));
+    let photos: Vec<Photo> = PHOTOS.lock().await.keys().cloned().collect();
+    tree_node_list.extend(height_skipped_node(&assign_stations(&options).await, &photos, &options));
     tree_node_list.extend_from_slice(station_tree_node_list.as_slice());
 
     let json = serde_json::to_string(&tree_node_list).map_err(|e|new_invoke_err(e.to_string().as_str()))?;
@@ -139,25 +468,124 @@ pub async fn calc_photo(radius: &str, photo_path: &str) -> Result<String, Invoke
 
 }
 
+/// 最近一次归属中每张照片的归属杆塔、归属方式和依据
+#[tauri::command]
+pub async fn photo_assignments() -> Result<String, InvokeError> {
+    let map = BELONG_MAP.lock().await.clone();
+    let mut list = vec![];
+
+    for station in ordered_stations().await.iter() {
+        if let Some(photo_map) = map.get(station) {
+            let mut photos: Vec<_> = photo_map.iter().collect();
+            photos.sort_by(|a, b| a.0.file_name.cmp(&b.0.file_name));
+
+            list.extend(photos.into_iter().map(|(photo, belong)| PhotoAssignment {
+                file_name: photo.file_name.clone(),
+                path: photo.path.clone(),
+                line: station.line.clone(),
+                station: station.name.clone(),
+                belong: belong.clone(),
+            }));
+        }
+    }
+
+    let json = serde_json::to_string(&list).map_err(|e|new_invoke_err(e.to_string().as_str()))?;
+
+    Ok(json)
+}
+
+/// 输出照片和视频片段, 返回处理失败的文件
 #[tauri::command]
-pub async fn move_to_output(output: &str) -> Result<(), InvokeError> {
+pub async fn move_to_output(output: &str, options: Option<OutputOptions>) -> Result<String, InvokeError> {
     // let _ = ensure_dir_exists(output).map_err(to_invoke_err)?;
+    let options = options.unwrap_or_default();
+    let template = options.template.clone();
+    *OUTPUT_PATH.lock().await = output.to_string();
+    if let Ok(mut roots) = THUMBNAIL_ROOTS.write() {
+        roots.output = output.to_string();
+    }
+    *OUTPUT_TEMPLATE.lock().await = template.clone();
     let output = Path::new(output);
     let map = BELONG_MAP.lock().await.clone();
+    let span_map = SPAN_MAP.lock().await.clone();
+    let video_segments = VIDEO_SEGMENTS.lock().await.clone();
+    let grouped = multi_line().await;
 
-    for (station, photo_map) in map.iter() {
+    // 杆塔照片输出到 <杆塔>, 通道照片输出到 <杆塔A>-<杆塔B>, 多条线路时外层再按线路分目录
+    let folders = map.iter().map(|(station, photo_map)| (station_folder(station, template.as_deref(), grouped), station.line.clone(), station.name.clone(), station.identity().to_string(), photo_map))
+        .chain(span_map.iter().map(|(span, photo_map)| (output_folder(&span.from.line, path_segment(&span.name()), grouped), span.from.line.clone(), span.name(), String::new(), photo_map)));
 
-        let station_path = output.join(station.name.clone());
+    let mut jobs = vec![];
+    for (folder, line, name, asset_id, photo_map) in folders {
+
+        let station_path = output.join(folder);
         let station_path_str = station_path.to_str().ok_or(new_invoke_err("station path is null"))?.to_string();
 
         ensure_dir_exists(station_path_str.as_str()).map_err(to_invoke_err)?;
-        for (photo, _) in photo_map.iter() {
+        let mut tags = vec![];
+        for (photo, belong) in photo_map.iter() {
             let dst_file = station_path.join(photo.file_name.as_str()).to_str().ok_or(new_invoke_err("dst file path is null"))?.to_string();;
-            fs::copy(photo.path.clone(), dst_file.as_str()).map_err(|e|new_invoke_err(e.to_string().as_str()))?;
+            if !options.overlay.enabled {
+                fs::copy(photo.path.clone(), dst_file.as_str()).map_err(|e|new_invoke_err(e.to_string().as_str()))?;
+            }
+
+            let tag = PhotoTag {
+                line: line.clone(),
+                station: name.clone(),
+                asset_id: asset_id.clone(),
+                distance: Some(belong.distance),
+                photo_type: photo.photo_type.label().to_string(),
+            };
+            tags.push((photo.file_name.clone(), tag.clone()));
+            jobs.push(OverlayJob { photo: photo.clone(), output: dst_file, tag });
+        }
+
+        if options.privacy.enabled() {
+            tags.sort_by(|a, b| a.0.cmp(&b.0));
+            write_sidecar(&station_path, &tags).map_err(to_invoke_err)?;
         }
     }
-    
-    Ok(())
+
+    // 叠加文字需要解码和重新编码, 在线程池中并行处理, 单张照片失败不影响其它照片
+    let mut failed: Vec<PhotoError> = vec![];
+    if options.overlay.enabled {
+        let jobs = jobs.clone();
+        let overlay = options.overlay.clone();
+        let privacy = options.privacy.clone();
+        failed = tokio::task::spawn_blocking(move || overlay_photos(&jobs, &overlay, &privacy)).await.map_err(|e|anyhow!(e)).map_err(to_invoke_err)?.map_err(to_invoke_err)?;
+    }
+
+    // 单张照片写入失败(如标记段超过 64KB)不影响其它照片
+    if options.write_tags || options.privacy.enabled() {
+        let pending: Vec<&OverlayJob> = jobs.iter().filter(|v| !failed.iter().any(|f| f.path == v.photo.path)).collect();
+        for job in pending {
+            if let Err(e) = tag_photo(job.output.as_str(), Some(&job.tag).filter(|_| options.write_tags), &options.privacy) {
+                // 隐私模式下没有删除定位信息的照片不能留在输出目录
+                if options.privacy.enabled() {
+                    let _ = fs::remove_file(&job.output);
+                }
+                failed.push(PhotoError::new(&job.photo.path, e));
+            }
+        }
+    }
+
+    // 视频片段列表和关键帧与杆塔照片放在同一目录
+    let mut keyframe_list = vec![];
+    for (station, segments) in video_segments.iter() {
+        let station_path = output.join(station_folder(station, template.as_deref(), grouped));
+        ensure_dir_exists(station_path.to_str().ok_or(new_invoke_err("station path is null"))?).map_err(to_invoke_err)?;
+        write_segments(&station_path, segments).map_err(to_invoke_err)?;
+        if options.keyframes {
+            keyframe_list.extend(keyframes(&station_path, segments));
+        }
+    }
+
+    // ffmpeg 截图较慢, 不占用异步运行时
+    failed.extend(tokio::task::spawn_blocking(move || extract_keyframes(&keyframe_list)).await.map_err(|e|anyhow!(e)).map_err(to_invoke_err)?);
+
+    let json = serde_json::to_string(&failed).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
 
 }
 
@@ -168,14 +596,14 @@ fn test1() {
 
     rt.block_on(async {
         let station_path = "C:\\Users\\yunyc\\Downloads\\福丰I线.kml";
-        kml_to_json(station_path).await.unwrap();
+        kml_to_json(station_path, None).await.unwrap();
 
         let radius = "100.0";
         let photo_input = "C:\\Users\\yunyc\\Downloads\\photo";
         let photo_output = "C:\\Users\\yunyc\\Downloads\\photo\\outpuuuu";
 
-        judge_photo_belong(radius, photo_input).await.unwrap();
-        move_to_output(photo_output).await.unwrap();
+        judge_photo_belong(radius, photo_input, &AssignOptions::default()).await.unwrap();
+        move_to_output(photo_output, None).await.unwrap();
     });
 }
 
@@ -185,12 +613,12 @@ fn test2() {
 
     rt.block_on(async {
         let station_path = "C:\\Users\\yunyc\\Downloads\\福丰I线.kml";
-        kml_to_json(station_path).await.unwrap();
+        kml_to_json(station_path, None).await.unwrap();
 
         let radius = "100.0";
         let photo_input = "C:\\Users\\yunyc\\Downloads\\photo";
 
-        let str = calc_photo(radius, photo_input).await.unwrap();
+        let str = calc_photo(radius, photo_input, None).await.unwrap();
         println!("{}",str);
     });
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::handle::{AssignMode, Belong, CalcPhotoResult, ONE_METERS_TO_LATITUDE, ONE_METERS_TO_LONGITUDE};
+use crate::photo::{Photo, PhotoType};
+use crate::station::{Station, TreeNode};
+
+/// 相邻两基杆塔之间的档
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub from: Station,
+    pub to: Station,
+}
+
+impl Span {
+    /// 输出目录名, 如 "#1-#2"
+    pub fn name(&self) -> String {
+        format!("{}-{}", self.from.name, self.to.name)
+    }
+
+    /// 多条线路中唯一的标识
+    pub fn key(&self) -> String {
+        if self.from.line.is_empty() {
+            self.name()
+        } else {
+            format!("{}/{}", self.from.line, self.name())
+        }
+    }
+
+    /// 照片到档中心线(两塔连线段)的距离(米)
+    pub fn distance_to(&self, photo: &Photo) -> f64 {
+        let to_meters = |longitude: f64, latitude: f64| {
+            ((longitude - self.from.longitude) / ONE_METERS_TO_LONGITUDE, (latitude - self.from.latitude) / ONE_METERS_TO_LATITUDE)
+        };
+        let (bx, by) = to_meters(self.to.longitude, self.to.latitude);
+        let (px, py) = to_meters(photo.longitude, photo.latitude);
+
+        let len = bx * bx + by * by;
+        let t = if len == 0.0 { 0.0 } else { ((px * bx + py * by) / len).clamp(0.0, 1.0) };
+        let (dx, dy) = (px - t * bx, py - t * by);
+
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+/// 按线路顺序将同一线路的相邻杆塔连成档
+pub fn spans(stations: &[Station]) -> Vec<Span> {
+    stations.windows(2)
+        .filter(|v| v[0].line == v[1].line)
+        .map(|v| Span { from: v[0].clone(), to: v[1].clone() })
+        .collect()
+}
+
+/// 通道巡检: 每张照片归属到距其最近的档, 超出通道宽度(中心线两侧各 buffer 米)的照片不归属
+pub fn assign_by_span(stations: &[Station], photos: &[Photo], buffer: f64) -> HashMap<Span, HashMap<Photo, Belong>> {
+    let spans = spans(stations);
+    let mut span_map: HashMap<Span, HashMap<Photo, Belong>> = HashMap::new();
+
+    for photo in photos.iter() {
+        let nearest = spans.iter()
+            .map(|v| (v, v.distance_to(photo)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((span, distance)) = nearest {
+            if distance <= buffer {
+                span_map.entry(span.clone()).or_default().insert(photo.clone(), Belong {
+                    distance,
+                    method: AssignMode::Span,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    span_map
+}
+
+/// 档级别的统计树, 结构与按杆塔统计的结果一致
+pub fn span_tree(stations: &[Station], span_map: &HashMap<Span, HashMap<Photo, Belong>>) -> Vec<TreeNode> {
+    let mut total_result = CalcPhotoResult::default();
+    let mut span_tree_node_list = vec![];
+
+    for span in spans(stations).iter() {
+        let photo_map = match span_map.get(span) {
+            Some(photo_map) => photo_map,
+            None => continue,
+        };
+
+        let mut cr = CalcPhotoResult::default();
+        for photo in photo_map.keys() {
+            match photo.photo_type {
+                PhotoType::Normal => cr.normal += 1,
+                PhotoType::Infrared => cr.infrared += 1,
+            }
+        }
+        total_result.normal += cr.normal;
+        total_result.infrared += cr.infrared;
+
+        span_tree_node_list.push(TreeNode {
+            key: span.key(),
+            label: span.name(),
+            children: Some(cr.to_tree_node()),
+        });
+    }
+
+    let mut tree_node_list = vec![TreeNode {
+        key: "total".to_string(),
+        label: "总数".to_string(),
+        children: Some(total_result.to_tree_node()),
+    }];
+    tree_node_list.extend(span_tree_node_list);
+
+    tree_node_list
+}
+
+#[test]
+fn test_assign_by_span() {
+    use crate::fixtures::{photo_at, station};
+
+    let stations = vec![station("#1", 120.0), station("#2", 120.004), station("#3", 120.008)];
+    let photos = vec![
+        // 1-2 档中间, 偏离中心线约 22 米
+        photo_at(120.002, 30.0002),
+        // 2-3 档, 偏离约 9 米
+        photo_at(120.006, 29.99992),
+        // 通道外约 90 米
+        photo_at(120.003, 30.0008),
+    ];
+
+    let span_map = assign_by_span(&stations, &photos, 30.0);
+    let spans = spans(&stations);
+
+    assert_eq!(spans[0].name(), "#1-#2");
+    assert_eq!(span_map[&spans[0]].len(), 1);
+    assert_eq!(span_map[&spans[1]].len(), 1);
+    assert!((span_map[&spans[0]][&photos[0]].distance - 22.2).abs() < 0.1);
+
+    let tree = span_tree(&stations, &span_map);
+    assert_eq!(tree.len(), 3);
+    assert_eq!(tree[1].key, "#1-#2");
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::handle::{assign_by_radius, AssignMode, AssignOptions, Belong, plane_distance};
+use crate::handle::elevation::assign_distance;
+use crate::photo::Photo;
+use crate::station::{Station, TreeNode};
+
+/// 无人机在一基杆塔附近连续拍摄形成的停留点
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Stop {
+    pub index: usize,
+    /// 首末照片的相机本地时间
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    /// 停留点中心经纬度
+    pub longitude: f64,
+    pub latitude: f64,
+    pub photo_count: usize,
+    /// 归属的杆塔, 为 Station::key()
+    pub station: Option<String>,
+    /// 中心到归属杆塔的距离(米)
+    pub distance: Option<f64>,
+    /// 次近的杆塔及距离, 用于判断归属是否可靠
+    pub second_station: Option<String>,
+    pub second_distance: Option<f64>,
+}
+
+impl Stop {
+    pub fn to_tree_node(&self) -> TreeNode {
+        TreeNode {
+            key: format!("stop-{}", self.index),
+            label: format!(
+                "停留点{}: {} ~ {}, {}张, 中心距离 {:.1}米",
+                self.index + 1,
+                self.start_time.as_deref().unwrap_or_default(),
+                self.end_time.as_deref().unwrap_or_default(),
+                self.photo_count,
+                self.distance.unwrap_or_default(),
+            ),
+            children: None,
+        }
+    }
+}
+
+/// 按拍摄时间排序后, 时间间隔或移动距离超过阈值处切分为停留点
+pub fn segment_stops(photos: &[Photo], options: &AssignOptions) -> Vec<Vec<Photo>> {
+    let mut timed: Vec<(i64, &Photo)> = photos.iter()
+        .filter_map(|v| v.capture_timestamp().map(|t| (t, v)))
+        .collect();
+    timed.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.file_name.cmp(&b.1.file_name)));
+
+    let mut segments: Vec<Vec<Photo>> = vec![];
+    let mut last: Option<(i64, &Photo)> = None;
+
+    for (time, photo) in timed.into_iter() {
+        let split = match last {
+            Some((last_time, last_photo)) => {
+                (time - last_time) as f64 > options.stop_time_gap
+                    || plane_distance(last_photo.longitude, last_photo.latitude, photo.longitude, photo.latitude) > options.stop_move_distance
+            }
+            None => true,
+        };
+
+        if split {
+            segments.push(vec![]);
+        }
+        segments.last_mut().unwrap().push(photo.clone());
+        last = Some((time, photo));
+    }
+
+    segments
+}
+
+/// 轨迹模式: 每个停留点整体归属到离其中心最近的杆塔, 没有拍摄时间的照片按半径归属
+pub fn assign_by_stops(
+    stations: &[(Station, f64)],
+    photos: &[Photo],
+    options: &AssignOptions,
+) -> (HashMap<Station, HashMap<Photo, Belong>>, Vec<Stop>) {
+    let untimed: Vec<Photo> = photos.iter().filter(|v| v.capture_timestamp().is_none()).cloned().collect();
+    let mut belong_map = assign_by_radius(stations, &untimed, options);
+    let mut stops = vec![];
+
+    for (index, segment) in segment_stops(photos, options).into_iter().enumerate() {
+        let longitude = segment.iter().map(|v| v.longitude).sum::<f64>() / segment.len() as f64;
+        let latitude = segment.iter().map(|v| v.latitude).sum::<f64>() / segment.len() as f64;
+
+        let mut nearest: Vec<(&Station, f64, f64)> = stations.iter()
+            .map(|(v, radius)| (v, plane_distance(v.longitude, v.latitude, longitude, latitude), *radius))
+            .collect();
+        nearest.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut stop = Stop {
+            index,
+            start_time: segment.first().and_then(|v| v.local_time.clone()),
+            end_time: segment.last().and_then(|v| v.local_time.clone()),
+            longitude,
+            latitude,
+            photo_count: segment.len(),
+            second_station: nearest.get(1).map(|v| v.0.key()),
+            second_distance: nearest.get(1).map(|v| v.1),
+            ..Default::default()
+        };
+
+        if let Some((station, distance, radius)) = nearest.first().cloned() {
+            if distance <= radius * options.stop_radius_factor {
+                stop.station = Some(station.key());
+                stop.distance = Some(distance);
+
+                let photo_map = belong_map.entry(station.clone()).or_default();
+                for photo in segment.into_iter() {
+                    // 高度不符合的照片(如起降时拍摄)不随停留点归属
+                    let distance = match assign_distance(station, &photo, options) {
+                        Some(distance) => distance,
+                        None => continue,
+                    };
+                    photo_map.insert(photo.clone(), Belong {
+                        distance,
+                        method: AssignMode::Trajectory,
+                        stop: Some(index),
+                        angular_error: None,
+                    });
+                }
+            }
+        }
+
+        stops.push(stop);
+    }
+
+    (belong_map, stops)
+}
+
+#[test]
+fn test_assign_by_stops() {
+    use crate::fixtures::{self, timed_photo};
+
+    let station = |name: &str, longitude: f64| (fixtures::station(name, longitude), 20.0);
+
+    // 两基塔相距约 88 米, 第一段拍摄位置离 1 号塔 30~40 米, 超出 20 米半径
+    let stations = vec![station("1", 120.0), station("2", 120.001)];
+    let photos = vec![
+        timed_photo("a", 120.00035, "2024-01-01 10:00:00"),
+        timed_photo("b", 120.00040, "2024-01-01 10:00:05"),
+        timed_photo("c", 120.00045, "2024-01-01 10:00:09"),
+        timed_photo("d", 120.00095, "2024-01-01 10:03:00"),
+        timed_photo("e", 120.00100, "2024-01-01 10:03:04"),
+    ];
+    let options = AssignOptions { mode: AssignMode::Trajectory, ..Default::default() };
+
+    let (belong_map, stops) = assign_by_stops(&stations, &photos, &options);
+
+    assert_eq!(stops.len(), 2);
+    assert_eq!(stops[0].station.as_deref(), Some("1"));
+    assert_eq!(stops[0].photo_count, 3);
+    assert_eq!(stops[1].station.as_deref(), Some("2"));
+    assert_eq!(belong_map[&stations[0].0].len(), 3);
+    assert_eq!(belong_map[&stations[1].0].len(), 2);
+}
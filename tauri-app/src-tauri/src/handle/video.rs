@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use tokio::sync::Mutex;
+use crate::handle::{assign_stations, plane_distance, LAST_ASSIGN, STATION_RADIUS};
+use crate::photo::PhotoError;
+use crate::photo::srt::{video_list, Video};
+use crate::station::{Station, TreeNode};
+use crate::station::line::multi_line;
+use crate::utils::{new_invoke_err, to_invoke_err};
+
+/// 每基杆塔的视频片段
+pub static VIDEO_SEGMENTS: Lazy<Mutex<HashMap<Station, Vec<VideoSegment>>>> = Lazy::new(|| {
+    Mutex::new(HashMap::new())
+});
+
+/// 相邻两帧间隔超过该值(秒)时分为两段
+pub static MAX_FRAME_GAP: f64 = 2.0;
+
+/// 短于该值(秒)的片段视为飞过, 不输出
+pub static MIN_SEGMENT_DURATION: f64 = 1.0;
+
+/// 片段列表的文件名
+pub static SEGMENT_FILE_NAME: &str = "视频片段.csv";
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct VideoSegment {
+    pub video: Option<String>,
+    pub file_name: String,
+    /// 片段起止位置(秒), 相对视频开头
+    pub start: f64,
+    pub end: f64,
+    /// 片段起止的相机本地时间
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    /// 片段内离杆塔最近的距离(米)
+    pub nearest_distance: f64,
+}
+
+impl VideoSegment {
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// 关键帧取片段中间位置, 文件名如 "DJI_0001_12.5s.jpg"
+    pub fn keyframe_name(&self) -> String {
+        let stem = Path::new(&self.file_name).file_stem().map(|v| v.to_string_lossy().to_string()).unwrap_or_default();
+        format!("{}_{:.1}s.jpg", stem, (self.start + self.end) / 2.0)
+    }
+
+    pub fn label(&self) -> String {
+        format!("{} {}-{} ({:.0}秒)", self.file_name, format_offset(self.start), format_offset(self.end), self.duration())
+    }
+}
+
+/// 秒数格式化为 "mm:ss"
+pub fn format_offset(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// 按大疆 SRT 字幕中的飞行数据, 找出飞机在各杆塔归属半径内的视频片段
+///
+/// 杆塔半径取最近一次照片归属的结果, 没有时取 radius
+#[tauri::command]
+pub async fn assign_videos(video_path: &str, radius: Option<f64>) -> Result<String, InvokeError> {
+    let last = LAST_ASSIGN.lock().await.clone();
+    let radius = radius.or(last.as_ref().map(|v| v.0)).ok_or(new_invoke_err("radius is null"))?;
+    let options = last.map(|v| v.1).unwrap_or_default();
+
+    let videos = video_list(video_path).map_err(to_invoke_err)?;
+    let stations = assign_stations(&options).await;
+    let station_radius = STATION_RADIUS.lock().await.clone();
+
+    let radius_of = |station: &Station| station_radius.get(station).cloned().or(station.radius).unwrap_or(radius);
+    let segments = assign_segments(&stations, &videos, radius_of);
+    *VIDEO_SEGMENTS.lock().await = segments.clone();
+
+    let grouped = multi_line().await;
+    let tree_node_list: Vec<TreeNode> = stations.iter().filter_map(|station| {
+        let segments = segments.get(station)?;
+        let children = segments.iter().enumerate().map(|(idx, v)| TreeNode {
+            key: format!("{}-video-{}", station.key(), idx),
+            label: v.label(),
+            children: None,
+        }).collect();
+
+        Some(TreeNode {
+            key: station.key(),
+            label: if grouped { station.key() } else { station.name.clone() },
+            children: Some(children),
+        })
+    }).collect();
+
+    let json = serde_json::to_string(&tree_node_list).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+pub fn assign_segments(stations: &[Station], videos: &[Video], radius_of: impl Fn(&Station) -> f64) -> HashMap<Station, Vec<VideoSegment>> {
+    let mut map: HashMap<Station, Vec<VideoSegment>> = HashMap::new();
+
+    for station in stations.iter() {
+        let radius = radius_of(station);
+        for video in videos.iter() {
+            let mut current: Option<VideoSegment> = None;
+            let mut finished = vec![];
+            for frame in video.frames.iter() {
+                let distance = plane_distance(station.longitude, station.latitude, frame.longitude, frame.latitude);
+                let inside = distance <= radius;
+
+                // 离开半径或帧间断开时结束当前片段
+                let ended = current.as_ref().is_some_and(|v| !inside || frame.offset - v.end > MAX_FRAME_GAP);
+                if ended {
+                    finished.extend(current.take());
+                }
+                if !inside {
+                    continue;
+                }
+
+                let segment = current.get_or_insert_with(|| VideoSegment {
+                    video: video.path.clone(),
+                    file_name: video.file_name.clone(),
+                    start: frame.offset,
+                    end: frame.offset,
+                    start_time: frame.time.clone(),
+                    end_time: frame.time.clone(),
+                    nearest_distance: distance,
+                });
+                segment.end = frame.offset;
+                segment.end_time = frame.time.clone();
+                segment.nearest_distance = segment.nearest_distance.min(distance);
+            }
+
+            finished.extend(current);
+            finished.retain(|v| v.duration() >= MIN_SEGMENT_DURATION);
+            if !finished.is_empty() {
+                map.entry(station.clone()).or_default().extend(finished);
+            }
+        }
+    }
+
+    map
+}
+
+/// 截取关键帧的任务
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub video: String,
+    /// 截取位置(秒), 相对视频开头
+    pub seconds: f64,
+    pub output: PathBuf,
+}
+
+/// 在杆塔目录中写入片段列表
+pub fn write_segments(folder: &Path, segments: &[VideoSegment]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(folder.join(SEGMENT_FILE_NAME))?;
+    writer.write_record(["视频", "开始(秒)", "结束(秒)", "开始时间", "结束时间", "最近距离(米)"])?;
+    for v in segments.iter() {
+        writer.write_record([v.file_name.clone(), format!("{:.1}", v.start), format!("{:.1}", v.end),
+            v.start_time.clone().unwrap_or_default(), v.end_time.clone().unwrap_or_default(), format!("{:.1}", v.nearest_distance)])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// 每个片段在杆塔目录中的关键帧
+pub fn keyframes(folder: &Path, segments: &[VideoSegment]) -> Vec<Keyframe> {
+    segments.iter().filter_map(|v| Some(Keyframe {
+        video: v.video.clone()?,
+        seconds: (v.start + v.end) / 2.0,
+        output: folder.join(v.keyframe_name()),
+    })).collect()
+}
+
+/// 依次截取关键帧, 单个失败不影响其他关键帧, 返回失败的视频及原因, 本机没有 ffmpeg 时不截取
+pub fn extract_keyframes(keyframes: &[Keyframe]) -> Vec<PhotoError> {
+    if keyframes.is_empty() {
+        return vec![];
+    }
+    if !ffmpeg_available() {
+        return vec![PhotoError { path: "ffmpeg".to_string(), error: "ffmpeg not found, keyframes are not extracted".to_string() }];
+    }
+
+    keyframes.iter()
+        .filter_map(|v| extract_keyframe(&v.video, v.seconds, &v.output).err().map(|e| PhotoError::new(&v.video, e)))
+        .collect()
+}
+
+fn ffmpeg_available() -> bool {
+    Command::new("ffmpeg").arg("-version").output().is_ok_and(|v| v.status.success())
+}
+
+fn extract_keyframe(video: &str, seconds: f64, output: &Path) -> anyhow::Result<()> {
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-ss", format!("{:.3}", seconds).as_str(), "-i", video, "-frames:v", "1", "-q:v", "2"])
+        .arg(output)
+        .status()?;
+
+    if !status.success() {
+        return Err(anyhow::Error::msg(format!("ffmpeg extract keyframe from {} failed", video)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_assign_segments() {
+    use crate::photo::srt::SrtFrame;
+    use crate::fixtures::station;
+
+    let stations = vec![station("1", 120.0), station("2", 120.01)];
+
+    // 每秒一帧, 由 1 号塔飞向 2 号塔, 20~25 秒之间字幕中断
+    let frames: Vec<SrtFrame> = (0..60).filter(|v| !(21..25).contains(v)).map(|v| SrtFrame {
+        offset: v as f64,
+        longitude: 120.0 + v as f64 * 0.0002,
+        latitude: 30.0,
+        ..Default::default()
+    }).collect();
+    let videos = vec![Video { path: Some("DJI_0001.MP4".to_string()), file_name: "DJI_0001.MP4".to_string(), frames, ..Default::default() }];
+
+    let segments = assign_segments(&stations, &videos, |_| 50.0);
+    let first = &segments[&stations[0]];
+    assert_eq!(first.len(), 1);
+    assert_eq!((first[0].start, first[0].end), (0.0, 2.0));
+    assert_eq!(first[0].keyframe_name(), "DJI_0001_1.0s.jpg");
+
+    let second = &segments[&stations[1]];
+    assert_eq!(second.len(), 1);
+    assert_eq!((second[0].start, second[0].end), (48.0, 52.0));
+    assert!(second[0].nearest_distance < 1.0);
+
+    let segments = assign_segments(&stations, &videos, |_| 1000.0);
+    assert_eq!(segments[&stations[1]].len(), 2);
+    assert_eq!(format_offset(62.4), "01:02");
+}
@@ -5,12 +5,31 @@ mod utils;
 mod station;
 mod photo;
 mod handle;
+mod report;
+mod analysis;
+#[cfg(test)]
+mod fixtures;
 
 use station::kml::{kml_to_excel, kml_to_json};
 use station::excel::excel_to_json;
+use station::order::set_station_order;
+use station::route::reconstruct_order;
+use station::line::{line_list, remove_line, set_line_info};
+use station::merge::{diff_ledgers, merge_ledgers};
+use station::edit::{edit_station, redo_station_edit, save_ledger, undo_station_edit};
 use handle::{
-    calc_photo,move_to_output
+    calc_photo,move_to_output,photo_assignments
 };
+use handle::video::assign_videos;
+use report::excel::export_excel_report;
+use report::html::export_html_report;
+use report::thermal::export_thermal_report;
+use analysis::radius::suggest_radius;
+use analysis::cluster::{apply_cluster, detect_clusters};
+use analysis::thermal::analyse_thermal;
+use photo::track::import_track;
+use photo::clock::{estimate_clock_offsets, set_clock_offset};
+use photo::thumbnail::{thumbnail_protocol, THUMBNAIL_SCHEME};
 
 #[tokio::main]
 async fn main() {
@@ -20,9 +39,33 @@ async fn main() {
             kml_to_excel,
             kml_to_json,
             excel_to_json,
+            set_station_order,
+            reconstruct_order,
+            line_list,
+            set_line_info,
+            remove_line,
+            merge_ledgers,
+            diff_ledgers,
+            edit_station,
+            undo_station_edit,
+            redo_station_edit,
+            save_ledger,
             calc_photo,
             move_to_output,
+            photo_assignments,
+            assign_videos,
+            export_excel_report,
+            export_html_report,
+            export_thermal_report,
+            suggest_radius,
+            detect_clusters,
+            apply_cluster,
+            analyse_thermal,
+            import_track,
+            set_clock_offset,
+            estimate_clock_offsets,
         ])
+        .register_uri_scheme_protocol(THUMBNAIL_SCHEME, thumbnail_protocol)
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
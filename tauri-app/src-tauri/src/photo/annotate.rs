@@ -0,0 +1,316 @@
+use std::fs;
+use std::io::Cursor;
+use anyhow::anyhow;
+use exif::{Context, Field, In, Reader, Tag, Value};
+use exif::experimental::Writer;
+use serde::{Deserialize, Serialize};
+use xml::escape::escape_str_pcdata;
+use crate::photo::thumbnail_data;
+use crate::photo::privacy::{privacy_field, privacy_xmp, PrivacyOptions};
+use crate::photo::jpeg::{find_xmp, join_jpeg, split_jpeg, Segment, APP0, APP1, EXIF_HEADER, XMP_HEADER};
+
+/// 写入 XMP 的自定义命名空间
+pub static TOWER_NAMESPACE: &str = "http://ns.tower-inspection/1.0/";
+
+pub static RDF_END: &str = "</rdf:RDF>";
+
+/// Windows 资源管理器显示的备注, UTF-16LE 编码
+pub static XP_COMMENT: Tag = Tag(Context::Tiff, 0x9C9C);
+
+/// UserComment 中 Unicode 文本的字符编码标识
+pub static UNICODE_PREFIX: &[u8; 8] = b"UNICODE\0";
+
+/// 写入输出照片的归属信息
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoTag {
+    pub line: String,
+    pub station: String,
+    pub asset_id: String,
+    /// 照片到杆塔的距离(米)
+    pub distance: Option<f64>,
+    /// 照片类型, 如 "普通"、"红外"
+    pub photo_type: String,
+}
+
+impl PhotoTag {
+    /// 完整的归属信息, 写入 EXIF UserComment 和 XPComment
+    pub fn description(&self) -> String {
+        let mut parts = vec![];
+        if !self.line.is_empty() {
+            parts.push(format!("线路: {}", self.line));
+        }
+        parts.push(format!("杆塔: {}", self.station));
+        if let Some(distance) = self.distance {
+            parts.push(format!("距离: {:.1}米", distance));
+        }
+        parts.push(format!("类型: {}", self.photo_type));
+
+        parts.join("; ")
+    }
+
+    /// 写入 EXIF ImageDescription 的文本, ImageDescription 只能是 ASCII, 只写入编号等不含中文的字段
+    pub fn ascii_description(&self) -> String {
+        let mut parts = vec![];
+        if self.station.is_ascii() {
+            parts.push(format!("Station: {}", self.station));
+        }
+        if !self.asset_id.is_empty() && self.asset_id.is_ascii() {
+            parts.push(format!("Asset: {}", self.asset_id));
+        }
+        if let Some(distance) = self.distance {
+            parts.push(format!("Distance: {:.1}m", distance));
+        }
+
+        parts.join("; ")
+    }
+
+    /// XMP 中的 rdf:Description, 关键字写入 dc:subject, 各字段写入自定义命名空间
+    pub fn xmp_description(&self) -> String {
+        let subjects: String = [&self.line, &self.station, &self.photo_type].iter()
+            .filter(|v| !v.is_empty())
+            .map(|v| format!("<rdf:li>{}</rdf:li>", escape_str_pcdata(v)))
+            .collect();
+        let distance = self.distance.map(|v| format!("<tower:Distance>{:.1}</tower:Distance>", v)).unwrap_or_default();
+
+        format!(
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:tower=\"{}\">\
+            <dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>\
+            <tower:Line>{}</tower:Line><tower:Station>{}</tower:Station><tower:AssetId>{}</tower:AssetId>{}<tower:PhotoType>{}</tower:PhotoType>\
+            </rdf:Description>",
+            TOWER_NAMESPACE, subjects,
+            escape_str_pcdata(&self.line), escape_str_pcdata(&self.station), escape_str_pcdata(&self.asset_id), distance, escape_str_pcdata(&self.photo_type),
+        )
+    }
+}
+
+/// 将归属信息写入照片的 EXIF 和 XMP, 并按隐私选项处理定位和设备信息, 只替换标记段, 不重新编码图像
+pub fn tag_photo(path: &str, tag: Option<&PhotoTag>, privacy: &PrivacyOptions) -> anyhow::Result<()> {
+    let bytes = fs::read(path)?;
+    let bytes = tag_jpeg(&bytes, tag, privacy)?;
+    fs::write(path, bytes)?;
+
+    Ok(())
+}
+
+pub fn tag_jpeg(bytes: &[u8], tag: Option<&PhotoTag>, privacy: &PrivacyOptions) -> anyhow::Result<Vec<u8>> {
+    let (mut segments, image_data) = split_jpeg(bytes)?;
+
+    let exif = exif_data(&segments, tag, privacy)?
+        .map(|data| Segment { marker: APP1, data });
+    let xmp = match (find_xmp(&segments), tag) {
+        (existing, Some(tag)) => Some(merge_xmp(existing, tag.xmp_description().as_str())),
+        (existing, None) => existing,
+    };
+    let xmp = xmp.map(|xmp| {
+        let mut data = XMP_HEADER.to_vec();
+        data.extend_from_slice(privacy_xmp(&xmp, privacy).as_bytes());
+        Segment { marker: APP1, data }
+    });
+
+    // EXIF 段放在最前面(JFIF 的 APP0 之后), XMP 紧随其后
+    segments.retain(|v| !(v.marker == APP1 && (v.data.starts_with(EXIF_HEADER) || v.data.starts_with(XMP_HEADER))));
+    let idx = segments.iter().take_while(|v| v.marker == APP0).count();
+    segments.splice(idx..idx, exif.into_iter().chain(xmp));
+
+    join_jpeg(&segments, image_data)
+}
+
+/// 重新编码并已按方向旋转的照片: Orientation 改为 1, 更新像素尺寸, 删除与新图像不一致的缩略图
+pub fn upright_jpeg(bytes: &[u8], width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+    let (mut segments, image_data) = split_jpeg(bytes)?;
+    let segment = match segments.iter_mut().find(|v| v.marker == APP1 && v.data.starts_with(EXIF_HEADER)) {
+        Some(segment) => segment,
+        None => return Ok(bytes.to_vec()),
+    };
+    let exif = Reader::new().read_raw(segment.data[EXIF_HEADER.len()..].to_vec()).map_err(|e|anyhow!(e))?;
+
+    let mut fields = vec![
+        Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![1]) },
+        Field { tag: Tag::PixelXDimension, ifd_num: In::PRIMARY, value: Value::Long(vec![width]) },
+        Field { tag: Tag::PixelYDimension, ifd_num: In::PRIMARY, value: Value::Long(vec![height]) },
+    ];
+    let replaced: Vec<Tag> = fields.iter().map(|v| v.tag).collect();
+    // 缩略图所在的 IFD1 整体去掉
+    fields.extend(exif.fields()
+        .filter(|v| v.ifd_num == In::PRIMARY && !replaced.contains(&v.tag))
+        .filter(|v| !matches!(v.value, Value::Unknown(..)))
+        .cloned());
+
+    let mut writer = Writer::new();
+    for field in fields.iter() {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(vec![]);
+    writer.write(&mut tiff, exif.little_endian()).map_err(|e|anyhow!(e))?;
+
+    segment.data = EXIF_HEADER.to_vec();
+    segment.data.extend_from_slice(tiff.get_ref());
+
+    join_jpeg(&segments, image_data)
+}
+
+/// 保留原有的 EXIF 字段, 有 tag 时替换 ImageDescription、UserComment 和 XPComment, 原照片没有 EXIF 且不写入归属时返回 None
+fn exif_data(segments: &[Segment], tag: Option<&PhotoTag>, privacy: &PrivacyOptions) -> anyhow::Result<Option<Vec<u8>>> {
+    let original = segments.iter()
+        .find(|v| v.marker == APP1 && v.data.starts_with(EXIF_HEADER))
+        .and_then(|v| Reader::new().read_raw(v.data[EXIF_HEADER.len()..].to_vec()).ok());
+    if original.is_none() && tag.is_none() {
+        return Ok(None);
+    }
+    let little_endian = original.as_ref().is_some_and(|v| v.little_endian());
+
+    let mut fields = tag.map(|v| description_fields(v, little_endian)).unwrap_or_default();
+    let replaced: Vec<Tag> = fields.iter().map(|v| v.tag).collect();
+    if let Some(original) = original.as_ref() {
+        // 无法识别类型的字段无法写回
+        fields.extend(original.fields()
+            .filter(|v| !(v.ifd_num == In::PRIMARY && replaced.contains(&v.tag)))
+            .filter(|v| !matches!(v.value, Value::Unknown(..)))
+            .filter_map(|v| privacy_field(v, privacy)));
+    }
+
+    let mut writer = Writer::new();
+    for field in fields.iter() {
+        writer.push_field(field);
+    }
+    if let Some(thumbnail) = original.as_ref().and_then(thumbnail_data) {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let mut tiff = Cursor::new(vec![]);
+    writer.write(&mut tiff, little_endian).map_err(|e|anyhow!(e))?;
+
+    let mut data = EXIF_HEADER.to_vec();
+    data.extend_from_slice(tiff.get_ref());
+
+    Ok(Some(data))
+}
+
+/// 归属信息的 EXIF 字段, 中文写入 UTF-16 编码的 UserComment 和 XPComment, 完整信息另见 XMP
+fn description_fields(tag: &PhotoTag, little_endian: bool) -> Vec<Field> {
+    let text = tag.description();
+    let utf16: Vec<u8> = text.encode_utf16()
+        .flat_map(|v| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() })
+        .collect();
+    let mut user_comment = UNICODE_PREFIX.to_vec();
+    user_comment.extend_from_slice(&utf16);
+    // XPComment 固定为小端并以 0 结尾
+    let mut xp_comment: Vec<u8> = text.encode_utf16().flat_map(|v| v.to_le_bytes()).collect();
+    xp_comment.extend_from_slice(&[0, 0]);
+
+    vec![
+        Field { tag: Tag::ImageDescription, ifd_num: In::PRIMARY, value: Value::Ascii(vec![tag.ascii_description().into_bytes()]) },
+        Field { tag: Tag::UserComment, ifd_num: In::PRIMARY, value: Value::Undefined(user_comment, 0) },
+        Field { tag: XP_COMMENT, ifd_num: In::PRIMARY, value: Value::Byte(xp_comment) },
+    ]
+}
+
+/// 在原有 XMP 中加入归属信息, 之前写入的归属信息会被替换, 原有的大疆字段保留
+fn merge_xmp(existing: Option<String>, description: &str) -> String {
+    let mut xmp = match existing {
+        Some(xmp) if xmp.contains(RDF_END) => xmp,
+        _ => format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+            <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">{}</x:xmpmeta>\
+            <?xpacket end=\"w\"?>", RDF_END),
+    };
+
+    let marker = format!("xmlns:tower=\"{}\"", TOWER_NAMESPACE);
+    if let Some(idx) = xmp.find(marker.as_str()) {
+        let start = xmp[..idx].rfind("<rdf:Description").unwrap_or(idx);
+        let end = xmp[idx..].find("</rdf:Description>").map(|v| idx + v + "</rdf:Description>".len());
+        if let Some(end) = end {
+            xmp.replace_range(start..end, "");
+        }
+    }
+
+    let idx = xmp.rfind(RDF_END).unwrap_or(xmp.len());
+    xmp.insert_str(idx, description);
+
+    xmp
+}
+
+#[test]
+fn test_tag_jpeg() {
+    use crate::photo::jpeg::{read_header_segments, xmp_f64, SOS};
+
+    let xmp = br#"<x:xmpmeta><rdf:RDF><rdf:Description drone-dji:GimbalYawDegree="-35.6"/></rdf:RDF></x:xmpmeta>"#;
+    let mut app1 = XMP_HEADER.to_vec();
+    app1.extend_from_slice(xmp);
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, APP1];
+    jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+    jpeg.extend_from_slice(&app1);
+    jpeg.extend_from_slice(&[0xFF, SOS, 0x00, 0x03, 0x01, 0x12, 0x34, 0xFF, 0xD9]);
+
+    let tag = PhotoTag {
+        line: "茶园线".to_string(),
+        station: "#12".to_string(),
+        asset_id: "T0012".to_string(),
+        distance: Some(8.26),
+        photo_type: "红外".to_string(),
+    };
+    let privacy = PrivacyOptions::default();
+    let tagged = tag_jpeg(&jpeg, Some(&tag), &privacy).unwrap();
+    // 重复写入时替换之前的归属信息
+    let tagged = tag_jpeg(&tagged, Some(&PhotoTag { station: "#13".to_string(), ..tag }), &privacy).unwrap();
+
+    assert!(tagged.ends_with(&[0xFF, SOS, 0x00, 0x03, 0x01, 0x12, 0x34, 0xFF, 0xD9]));
+
+    let exif = Reader::new().read_from_container(&mut Cursor::new(&tagged)).unwrap();
+    let description = exif.get_field(Tag::ImageDescription, In::PRIMARY).unwrap();
+    let description = match &description.value {
+        Value::Ascii(v) => String::from_utf8(v[0].clone()).unwrap(),
+        _ => String::new(),
+    };
+    assert_eq!(description, "Station: #13; Asset: T0012; Distance: 8.3m");
+
+    let comment = exif.get_field(Tag::UserComment, In::PRIMARY).unwrap();
+    let comment = match &comment.value {
+        Value::Undefined(v, _) if v.starts_with(UNICODE_PREFIX) => {
+            let utf16: Vec<u16> = v[UNICODE_PREFIX.len()..].chunks_exact(2).map(|v| u16::from_be_bytes([v[0], v[1]])).collect();
+            String::from_utf16(&utf16).unwrap()
+        }
+        _ => String::new(),
+    };
+    assert_eq!(comment, "线路: 茶园线; 杆塔: #13; 距离: 8.3米; 类型: 红外");
+    assert_eq!(exif.fields().filter(|v| v.tag == XP_COMMENT).count(), 1);
+
+    let segments = read_header_segments(&mut tagged.as_slice()).unwrap();
+    let xmp = find_xmp(&segments).unwrap();
+    assert_eq!(xmp_f64(&xmp, "drone-dji:GimbalYawDegree"), Some(-35.6));
+    assert_eq!(xmp.matches("<tower:Station>").count(), 1);
+    assert!(xmp.contains("<tower:Station>#13</tower:Station>"));
+    assert!(xmp.contains("<rdf:li>茶园线</rdf:li>"));
+
+    // 标记段超过 65533 字节时返回错误, 由调用方记为该照片失败
+    let oversized = PhotoTag { station: "#".repeat(70000), ..Default::default() };
+    assert!(tag_jpeg(&jpeg, Some(&oversized), &privacy).is_err());
+}
+
+#[test]
+fn test_upright_jpeg() {
+    use crate::photo::jpeg::SOS;
+
+    let mut writer = Writer::new();
+    let orientation = Field { tag: Tag::Orientation, ifd_num: In::PRIMARY, value: Value::Short(vec![6]) };
+    let model = Field { tag: Tag::Model, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"M3T".to_vec()]) };
+    writer.push_field(&orientation);
+    writer.push_field(&model);
+    let thumbnail = [0xFF, 0xD8, 0xFF, 0xD9];
+    writer.set_jpeg(&thumbnail, In::THUMBNAIL);
+    let mut tiff = Cursor::new(vec![]);
+    writer.write(&mut tiff, false).unwrap();
+
+    let mut app1 = EXIF_HEADER.to_vec();
+    app1.extend_from_slice(tiff.get_ref());
+    let segments = vec![Segment { marker: APP1, data: app1 }];
+    let jpeg = join_jpeg(&segments, &[0xFF, SOS, 0x00, 0x03, 0x01, 0x12, 0x34, 0xFF, 0xD9]).unwrap();
+
+    let upright = upright_jpeg(&jpeg, 300, 400).unwrap();
+    let exif = Reader::new().read_from_container(&mut Cursor::new(&upright)).unwrap();
+    assert_eq!(exif.get_field(Tag::Orientation, In::PRIMARY).and_then(|v| v.value.get_uint(0)), Some(1));
+    assert_eq!(exif.get_field(Tag::PixelXDimension, In::PRIMARY).and_then(|v| v.value.get_uint(0)), Some(300));
+    assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+    assert!(!exif.fields().any(|v| v.ifd_num == In::THUMBNAIL));
+    assert!(thumbnail_data(&exif).is_none());
+}
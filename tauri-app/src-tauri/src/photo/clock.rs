@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDateTime};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use tokio::sync::Mutex;
+use crate::photo::{photo_list, Photo, PHOTOS, PHOTOS_PATH};
+use crate::utils::to_invoke_err;
+
+/// 拍摄时间的格式
+pub static TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub static CLOCK: Lazy<Mutex<ClockSettings>> = Lazy::new(|| { Mutex::new(ClockSettings::default()) });
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ClockSettings {
+    /// 各相机(按序列号)本地时间减 UTC 的秒数, 包含时区和相机时钟偏差
+    pub offsets: HashMap<String, f64>,
+    /// 相机没有校准、EXIF 中也没有时区时使用的时区偏移(秒), 默认北京时间
+    pub default_offset: f64,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        ClockSettings {
+            offsets: HashMap::new(),
+            default_offset: 8.0 * 3600.0,
+        }
+    }
+}
+
+impl ClockSettings {
+    /// 优先取相机的校准值, 其次取 EXIF 中的时区
+    pub fn offset(&self, photo: &Photo) -> f64 {
+        photo.camera.as_ref().and_then(|v| self.offsets.get(v)).cloned()
+            .or(photo.utc_offset)
+            .unwrap_or(self.default_offset)
+    }
+
+    /// 将相机记录的本地时间换算为 UTC 写入 capture_time
+    pub fn normalize(&self, photo: &mut Photo) {
+        let offset = self.offset(photo);
+        photo.capture_time = photo.local_timestamp()
+            .and_then(|v| DateTime::from_timestamp(v - offset.round() as i64, 0))
+            .map(|v| v.naive_utc().format(TIME_FORMAT).to_string());
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct CameraClock {
+    pub camera: String,
+    /// 本地时间减 UTC 的秒数
+    pub offset: f64,
+    /// 同时有拍摄时间和 GPS 时间的照片数
+    pub samples: usize,
+    /// 各照片偏差的最大值与最小值之差(秒), 偏大说明拍摄期间时钟被调整过
+    pub spread: f64,
+}
+
+/// 设置相机的时钟偏差(秒), 不指定相机时设置默认时区偏移, 设置后重新读取照片
+#[tauri::command]
+pub async fn set_clock_offset(camera: Option<String>, offset: f64) -> Result<(), InvokeError> {
+    {
+        let mut clock = CLOCK.lock().await;
+        match camera {
+            Some(camera) => {
+                clock.offsets.insert(camera, offset);
+            }
+            None => clock.default_offset = offset,
+        }
+    }
+
+    reload_photos().await.map_err(to_invoke_err)?;
+
+    Ok(())
+}
+
+/// 按 GPS 时间估计每台相机的时钟偏差, apply 为 true 时保存并重新读取照片
+#[tauri::command]
+pub async fn estimate_clock_offsets(apply: Option<bool>) -> Result<String, InvokeError> {
+    let photos: Vec<Photo> = PHOTOS.lock().await.keys().cloned().collect();
+    let clocks = estimate_offsets(&photos);
+
+    if apply.unwrap_or_default() {
+        CLOCK.lock().await.offsets.extend(clocks.iter().map(|v| (v.camera.clone(), v.offset)));
+        reload_photos().await.map_err(to_invoke_err)?;
+    }
+
+    let json = serde_json::to_string(&clocks).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+async fn reload_photos() -> anyhow::Result<()> {
+    let path = PHOTOS_PATH.lock().await.clone();
+    if !path.is_empty() {
+        photo_list(path.as_str()).await?;
+    }
+
+    Ok(())
+}
+
+/// 每台相机取拍摄时间与 GPS 时间之差的中位数
+pub fn estimate_offsets(photos: &[Photo]) -> Vec<CameraClock> {
+    let mut samples: HashMap<String, Vec<f64>> = HashMap::new();
+    for photo in photos.iter() {
+        if let (Some(camera), Some(local), Some(gps)) = (photo.camera.as_ref(), photo.local_timestamp(), photo.gps_time) {
+            samples.entry(camera.clone()).or_default().push((local - gps) as f64);
+        }
+    }
+
+    let mut clocks: Vec<CameraClock> = samples.into_iter().map(|(camera, mut values)| {
+        values.sort_by(f64::total_cmp);
+        CameraClock {
+            camera,
+            offset: values[values.len() / 2],
+            samples: values.len(),
+            spread: values[values.len() - 1] - values[0],
+        }
+    }).collect();
+    clocks.sort_by(|a, b| a.camera.cmp(&b.camera));
+
+    clocks
+}
+
+/// 解析 EXIF OffsetTimeOriginal, 如 "+08:00", 返回秒数
+pub fn parse_utc_offset(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let sign = match text.chars().next()? {
+        '+' => 1.0,
+        '-' => -1.0,
+        _ => return None,
+    };
+    let (hours, minutes) = text[1..].split_once(':')?;
+
+    Some(sign * (hours.parse::<f64>().ok()? * 3600.0 + minutes.parse::<f64>().ok()? * 60.0))
+}
+
+/// 解析 EXIF 中的 GPS 日期和时间(UTC)
+pub fn gps_timestamp(date: &str, hours: f64, minutes: f64, seconds: f64) -> Option<i64> {
+    let date = NaiveDateTime::parse_from_str(format!("{} 00:00:00", date.trim()).as_str(), "%Y:%m:%d %H:%M:%S").ok()?;
+
+    Some(date.and_utc().timestamp() + (hours * 3600.0 + minutes * 60.0 + seconds).round() as i64)
+}
+
+#[test]
+fn test_clock() {
+    let photo = |camera: &str, local: &str, gps: &str| Photo {
+        camera: Some(camera.to_string()),
+        local_time: Some(local.to_string()),
+        gps_time: NaiveDateTime::parse_from_str(gps, TIME_FORMAT).ok().map(|v| v.and_utc().timestamp()),
+        ..Default::default()
+    };
+
+    // A 相机为北京时间且快 5 秒
+    let photos = vec![
+        photo("A", "2024-01-01 10:00:05", "2024-01-01 02:00:00"),
+        photo("A", "2024-01-01 10:01:06", "2024-01-01 02:01:00"),
+        photo("A", "2024-01-01 10:02:05", "2024-01-01 02:02:00"),
+        photo("B", "2024-01-01 02:00:00", "2024-01-01 02:00:00"),
+    ];
+    let clocks = estimate_offsets(&photos);
+    assert_eq!(clocks.len(), 2);
+    assert_eq!(clocks[0].offset, 28805.0);
+    assert_eq!(clocks[0].spread, 1.0);
+    assert_eq!(clocks[1].offset, 0.0);
+
+    let mut settings = ClockSettings::default();
+    settings.offsets.insert("A".to_string(), clocks[0].offset);
+    let mut photo = photos[1].clone();
+    settings.normalize(&mut photo);
+    assert_eq!(photo.capture_time.as_deref(), Some("2024-01-01 02:01:01"));
+
+    // 没有校准时按 EXIF 时区, 再按默认时区
+    let mut photo = Photo { local_time: Some("2024-01-01 10:00:00".to_string()), utc_offset: parse_utc_offset("+09:00"), ..Default::default() };
+    settings.normalize(&mut photo);
+    assert_eq!(photo.capture_time.as_deref(), Some("2024-01-01 01:00:00"));
+    photo.utc_offset = None;
+    settings.normalize(&mut photo);
+    assert_eq!(photo.capture_time.as_deref(), Some("2024-01-01 02:00:00"));
+
+    assert_eq!(parse_utc_offset("-05:30"), Some(-19800.0));
+    assert_eq!(gps_timestamp("2024:01:01", 2.0, 1.0, 0.0), NaiveDateTime::parse_from_str("2024-01-01 02:01:00", TIME_FORMAT).ok().map(|v| v.and_utc().timestamp()));
+}
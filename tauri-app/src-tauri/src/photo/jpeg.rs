@@ -0,0 +1,166 @@
+use std::io::{Cursor, Read};
+
+/// JPEG 标记段
+pub static SOI: u8 = 0xD8;
+pub static SOS: u8 = 0xDA;
+pub static EOI: u8 = 0xD9;
+pub static APP0: u8 = 0xE0;
+pub static APP1: u8 = 0xE1;
+/// 大疆 R-JPEG 的辐射原始数据保存在 APP3 段, 数据较大时分为多个段
+pub static APP3: u8 = 0xE3;
+/// 基线、扩展、渐进 JPEG 的帧头
+pub static SOF: [u8; 3] = [0xC0, 0xC1, 0xC2];
+
+/// XMP 所在 APP1 段的标识
+pub static XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// EXIF 所在 APP1 段的标识
+pub static EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// 标记段数据的最大长度(长度字段本身占 2 字节)
+pub static MAX_SEGMENT_LEN: usize = 65533;
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub marker: u8,
+    /// 不含长度字段的段数据
+    pub data: Vec<u8>,
+}
+
+/// 读取 SOS 之前的所有标记段, 不读取图像数据
+pub fn read_header_segments<R: Read>(reader: &mut R) -> anyhow::Result<Vec<Segment>> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    if buf != [0xFF, SOI] {
+        return Err(anyhow::Error::msg("not jpeg file"));
+    }
+
+    let mut segments = vec![];
+    loop {
+        reader.read_exact(&mut buf)?;
+        if buf[0] != 0xFF {
+            return Err(anyhow::Error::msg("invalid jpeg marker"));
+        }
+        let marker = buf[1];
+        if marker == SOS || marker == EOI {
+            break;
+        }
+
+        reader.read_exact(&mut buf)?;
+        let len = u16::from_be_bytes(buf) as usize;
+        if len < 2 {
+            return Err(anyhow::Error::msg("invalid jpeg segment length"));
+        }
+        let mut data = vec![0u8; len - 2];
+        reader.read_exact(&mut data)?;
+
+        segments.push(Segment { marker, data });
+    }
+
+    Ok(segments)
+}
+
+/// 拆分 JPEG 文件, 返回 SOS 之前的标记段和从 SOS 开始的图像数据
+pub fn split_jpeg(bytes: &[u8]) -> anyhow::Result<(Vec<Segment>, &[u8])> {
+    let mut cursor = Cursor::new(bytes);
+    let segments = read_header_segments(&mut cursor)?;
+    // 读取标记段时已读过 SOS 标记本身
+    let start = cursor.position() as usize - 2;
+
+    Ok((segments, &bytes[start..]))
+}
+
+/// 由标记段和图像数据重新组成 JPEG 文件, 不重新编码图像
+pub fn join_jpeg(segments: &[Segment], image_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![0xFF, SOI];
+    for segment in segments.iter() {
+        if segment.data.len() > MAX_SEGMENT_LEN {
+            return Err(anyhow::Error::msg("jpeg segment too large"));
+        }
+        bytes.extend_from_slice(&[0xFF, segment.marker]);
+        bytes.extend_from_slice(&((segment.data.len() + 2) as u16).to_be_bytes());
+        bytes.extend_from_slice(&segment.data);
+    }
+    bytes.extend_from_slice(image_data);
+
+    Ok(bytes)
+}
+
+/// APP0~APP15 段, 保存 EXIF、XMP 和厂商数据
+pub fn is_app(marker: u8) -> bool {
+    (APP0..=0xEF).contains(&marker)
+}
+
+/// 用原照片的 APP 段替换重新编码后照片的 APP 段, 保留 EXIF、XMP 和红外原始数据
+pub fn keep_metadata(original: &[u8], encoded: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (original, _) = split_jpeg(original)?;
+    let (encoded, image_data) = split_jpeg(encoded)?;
+
+    let segments: Vec<Segment> = original.into_iter().filter(|v| is_app(v.marker))
+        .chain(encoded.into_iter().filter(|v| !is_app(v.marker)))
+        .collect();
+
+    join_jpeg(&segments, image_data)
+}
+
+/// 从标记段中取出 XMP 文本
+pub fn find_xmp(segments: &[Segment]) -> Option<String> {
+    segments.iter()
+        .find(|v| v.marker == APP1 && v.data.starts_with(XMP_HEADER))
+        .map(|v| String::from_utf8_lossy(&v.data[XMP_HEADER.len()..]).to_string())
+}
+
+/// 按顺序拼接所有 APP3 段的数据, 没有 APP3 段时返回 None
+pub fn find_app3(segments: &[Segment]) -> Option<Vec<u8>> {
+    let data: Vec<u8> = segments.iter()
+        .filter(|v| v.marker == APP3)
+        .flat_map(|v| v.data.iter().cloned())
+        .collect();
+
+    if data.is_empty() { None } else { Some(data) }
+}
+
+/// 从帧头中读取图像宽高
+pub fn frame_size(segments: &[Segment]) -> Option<(usize, usize)> {
+    let frame = segments.iter().find(|v| SOF.contains(&v.marker) && v.data.len() >= 5)?;
+    let height = u16::from_be_bytes([frame.data[1], frame.data[2]]) as usize;
+    let width = u16::from_be_bytes([frame.data[3], frame.data[4]]) as usize;
+
+    Some((width, height))
+}
+
+/// 读取 XMP 中的数值, 兼容属性 `drone-dji:Key="+1.0"` 和元素 `<drone-dji:Key>+1.0</drone-dji:Key>` 两种写法
+pub fn xmp_f64(xmp: &str, key: &str) -> Option<f64> {
+    let attr = format!("{}=\"", key);
+    if let Some(idx) = xmp.find(attr.as_str()) {
+        let rest = &xmp[idx + attr.len()..];
+        let end = rest.find('"')?;
+        return rest[..end].trim().parse().ok();
+    }
+
+    let tag = format!("<{}>", key);
+    let idx = xmp.find(tag.as_str())?;
+    let rest = &xmp[idx + tag.len()..];
+    let end = rest.find('<')?;
+    rest[..end].trim().parse().ok()
+}
+
+#[test]
+fn test_read_xmp() {
+    let xmp = br#"<x:xmpmeta><rdf:Description drone-dji:AbsoluteAltitude="+123.45" drone-dji:RelativeAltitude="-2.10"><drone-dji:GimbalYawDegree>-35.6</drone-dji:GimbalYawDegree></rdf:Description></x:xmpmeta>"#;
+
+    let mut app1 = XMP_HEADER.to_vec();
+    app1.extend_from_slice(xmp);
+    let mut jpeg = vec![0xFF, SOI, 0xFF, APP1];
+    jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+    jpeg.extend_from_slice(&app1);
+    jpeg.extend_from_slice(&[0xFF, SOS, 0x00, 0x02]);
+
+    let segments = read_header_segments(&mut jpeg.as_slice()).unwrap();
+    let xmp = find_xmp(&segments).unwrap();
+
+    assert_eq!(xmp_f64(&xmp, "drone-dji:AbsoluteAltitude"), Some(123.45));
+    assert_eq!(xmp_f64(&xmp, "drone-dji:RelativeAltitude"), Some(-2.1));
+    assert_eq!(xmp_f64(&xmp, "drone-dji:GimbalYawDegree"), Some(-35.6));
+    assert_eq!(xmp_f64(&xmp, "drone-dji:FlightYawDegree"), None);
+}
@@ -3,18 +3,41 @@ use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::BufReader;
 use std::path::Path;
-use exif::{Exif, Field, In, Reader, Tag};
+use chrono::NaiveDateTime;
+use exif::{DateTime, Exif, Field, In, Reader, Tag, Value};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tauri::InvokeError;
 use tokio::fs;
 use tokio::sync::Mutex;
+use crate::analysis::thermal::THERMAL;
+use crate::station::TreeNode;
 use crate::utils::{file_name, to_invoke_err};
+use crate::photo::jpeg::{find_xmp, read_header_segments, xmp_f64};
+use crate::photo::clock::{gps_timestamp, parse_utc_offset, CLOCK, TIME_FORMAT};
+use crate::photo::track::TRACK;
+use crate::photo::thumbnail::THUMBNAIL_ROOTS;
+
+pub mod jpeg;
+pub mod annotate;
+pub mod privacy;
+pub mod overlay;
+pub mod thumbnail;
+pub mod clock;
+pub mod srt;
+pub mod thermal;
+pub mod track;
 
 pub static PHOTOS: Lazy<Mutex<HashMap<Photo, bool>>> = Lazy::new(|| { Mutex::new(HashMap::new()) });
 
 pub static PHOTOS_PATH: Lazy<Mutex<String>> = Lazy::new(|| {Mutex::new(String::new())});
 
+/// 没有 GPS 信息且无法按轨迹定位的照片, 不参与归属
+pub static UNLOCATED: Lazy<Mutex<Vec<Photo>>> = Lazy::new(|| {Mutex::new(vec![])});
+
+/// 无法读取的照片, 如文件损坏或没有 EXIF, 不参与归属
+pub static FAILED: Lazy<Mutex<Vec<PhotoError>>> = Lazy::new(|| {Mutex::new(vec![])});
+
 #[derive(Default, Debug, Serialize, Deserialize, Clone,PartialEq,Eq,Hash)]
 pub enum PhotoType {
     #[default]
@@ -22,6 +45,24 @@ pub enum PhotoType {
     Infrared,
 }
 
+impl PhotoType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PhotoType::Normal => "普通",
+            PhotoType::Infrared => "红外",
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionSource {
+    /// EXIF 中的 GPS 信息
+    #[default]
+    Exif,
+    /// 按拍摄时间从飞行轨迹插值
+    Track,
+    Missing,
+}
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct Photo {
@@ -30,6 +71,68 @@ pub struct Photo {
     pub photo_type: PhotoType,
     pub path: String,
     pub file_name: String,
+    /// 拍摄时间(UTC), 由相机本地时间按时钟偏差换算, 格式 "YYYY-MM-DD HH:MM:SS"
+    pub capture_time: Option<String>,
+    /// 相机记录的拍摄时间 DateTimeOriginal, 没有时区
+    #[serde(default)]
+    pub local_time: Option<String>,
+    /// EXIF OffsetTimeOriginal 中的时区偏移(秒)
+    #[serde(default)]
+    pub utc_offset: Option<f64>,
+    /// EXIF 中的 GPS 时间(UTC 时间戳)
+    #[serde(default)]
+    pub gps_time: Option<i64>,
+    /// 相机序列号, 没有时取相机型号
+    #[serde(default)]
+    pub camera: Option<String>,
+    /// 拍摄位置海拔(米), 优先取大疆 XMP 中的绝对高度
+    pub altitude: Option<f64>,
+    /// 相对起飞点高度(米)
+    pub relative_altitude: Option<f64>,
+    /// 云台偏航角(度), 正北为 0, 顺时针为正
+    pub gimbal_yaw: Option<f64>,
+    /// 云台俯仰角(度), 向下为负
+    pub gimbal_pitch: Option<f64>,
+    #[serde(default)]
+    pub position_source: PositionSource,
+}
+
+/// 读取或处理失败的照片及原因
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct PhotoError {
+    pub path: String,
+    pub error: String,
+}
+
+/// 扫描照片目录的结果
+#[derive(Default, Debug, Clone)]
+pub struct PhotoScan {
+    /// 已定位的照片
+    pub photos: HashMap<Photo, bool>,
+    /// 没有 GPS 信息且无法按轨迹定位的照片
+    pub unlocated: Vec<Photo>,
+    /// 无法读取的照片
+    pub failed: Vec<PhotoError>,
+}
+
+impl PhotoError {
+    pub fn new(path: &str, error: anyhow::Error) -> Self {
+        PhotoError { path: path.to_string(), error: error.to_string() }
+    }
+}
+
+impl Photo {
+    /// 拍摄时间转为秒级时间戳
+    pub fn capture_timestamp(&self) -> Option<i64> {
+        let time = self.capture_time.as_ref()?;
+        NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok().map(|v| v.and_utc().timestamp())
+    }
+
+    /// 相机本地时间按 UTC 转为时间戳, 用于估计时钟偏差
+    pub fn local_timestamp(&self) -> Option<i64> {
+        let time = self.local_time.as_ref()?;
+        NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok().map(|v| v.and_utc().timestamp())
+    }
 }
 
 impl Eq for Photo {}
@@ -52,30 +155,81 @@ impl Hash for Photo {
     }
 }
 
+/// 读取照片目录, 返回无法读取的照片
 #[tauri::command]
-pub async fn input_photos(path: &str) -> Result<(), InvokeError> {
-    let _ = photo_list(path).await.map_err(to_invoke_err)?;
+pub async fn input_photos(path: &str) -> Result<String, InvokeError> {
+    let scan = photo_list(path).await.map_err(to_invoke_err)?;
+    let json = serde_json::to_string(&scan.failed).map_err(|e|anyhow::anyhow!(e)).map_err(to_invoke_err)?;
 
-    Ok(())
+    Ok(json)
 }
 
-pub async fn photo_list(path: &str) -> anyhow::Result<HashMap<Photo, bool>> {
+/// 读取目录下的照片并作为当前照片保存
+pub async fn photo_list(path: &str) -> anyhow::Result<PhotoScan> {
+    let scan = scan_photos(path).await?;
+
+    *PHOTOS.lock().await = scan.photos.clone();
+    *UNLOCATED.lock().await = scan.unlocated.clone();
+    *FAILED.lock().await = scan.failed.clone();
+    *PHOTOS_PATH.lock().await = path.to_string();
+    if let Ok(mut roots) = THUMBNAIL_ROOTS.write() {
+        roots.photos = path.to_string();
+    }
+    // 测温结果属于之前加载的照片
+    THERMAL.lock().await.clear();
+
+    Ok(scan)
+}
+
+/// 读取目录下的照片, 不修改当前照片, 供分析命令使用
+pub async fn scan_photos(path: &str) -> anyhow::Result<PhotoScan> {
     let mut entries = fs::read_dir(path).await?;
 
-    let mut map = HashMap::new();
+    let mut scan = PhotoScan::default();
+    let track = TRACK.lock().await.clone();
+    let clock = CLOCK.lock().await.clone();
 
     while let Some(entry) = entries.next_entry().await? {
         let path = entry.path();
         if path.is_file() && is_photo(&path) {
-            let photo = get_photo(path.to_str().unwrap())?;
-            map.insert(photo, true);
+            let path = path.to_string_lossy().to_string();
+            // 单张照片读取失败不影响其他照片
+            let mut photo = match get_photo(path.as_str()) {
+                Ok(photo) => photo,
+                Err(e) => {
+                    scan.failed.push(PhotoError::new(&path, e));
+                    continue;
+                }
+            };
+            clock.normalize(&mut photo);
+            if photo.position_source == PositionSource::Missing && !track.as_ref().is_some_and(|v| v.geotag(&mut photo)) {
+                scan.unlocated.push(photo);
+                continue;
+            }
+            scan.photos.insert(photo, true);
         }
     }
 
-    *PHOTOS.lock().await = map.clone();
-    *PHOTOS_PATH.lock().await = path.to_string();
+    Ok(scan)
+}
+
+/// 读取失败的照片的树节点, 没有时返回 None
+pub fn failed_node(failed: &[PhotoError]) -> Option<TreeNode> {
+    if failed.is_empty() {
+        return None;
+    }
+
+    let children = failed.iter().map(|v| TreeNode {
+        key: v.path.clone(),
+        label: format!("{}: {}", file_name(&v.path).unwrap_or(v.path.clone()), v.error),
+        children: None,
+    }).collect();
 
-    Ok(map)
+    Some(TreeNode {
+        key: "failed".to_string(),
+        label: format!("读取失败: {}", failed.len()),
+        children: Some(children),
+    })
 }
 
 
@@ -94,10 +248,25 @@ fn get_photo(path: &str) -> anyhow::Result<Photo> {
     let mut reader = BufReader::new(&mut file);
     let exif_data = Reader::new().read_from_container(&mut reader)?;
 
-    let longitude = get_gps_info(&exif_data, Tag::GPSLongitude)?;
-    let latitude = get_gps_info(&exif_data, Tag::GPSLatitude)?;
+    // 部分红外相机不写 GPS 信息, 之后按飞行轨迹定位
+    let position = get_gps_info(&exif_data, Tag::GPSLongitude).and_then(|lon| Ok((lon, get_gps_info(&exif_data, Tag::GPSLatitude)?)));
+    let (longitude, latitude, position_source) = match position {
+        Ok((longitude, latitude)) => (longitude, latitude, PositionSource::Exif),
+        Err(_) => (0.0, 0.0, PositionSource::Missing),
+    };
     let photo_type = get_photo_type(path)?;
     let photo_name = file_name(path)? + ".JPG";
+    let local_time = get_capture_time(&exif_data);
+    let utc_offset = get_ascii(&exif_data, Tag::OffsetTimeOriginal).and_then(|v| parse_utc_offset(&v));
+    let gps_time = get_gps_time(&exif_data);
+    let camera = get_ascii(&exif_data, Tag::BodySerialNumber).or(get_ascii(&exif_data, Tag::Model));
+
+    let segments = read_header_segments(&mut BufReader::new(File::open(path)?)).unwrap_or_default();
+    let xmp = find_xmp(&segments).unwrap_or_default();
+    let altitude = xmp_f64(&xmp, "drone-dji:AbsoluteAltitude").or(get_gps_altitude(&exif_data));
+    let relative_altitude = xmp_f64(&xmp, "drone-dji:RelativeAltitude");
+    let gimbal_yaw = xmp_f64(&xmp, "drone-dji:GimbalYawDegree");
+    let gimbal_pitch = xmp_f64(&xmp, "drone-dji:GimbalPitchDegree");
 
     Ok(Photo{
         longitude,
@@ -105,10 +274,38 @@ fn get_photo(path: &str) -> anyhow::Result<Photo> {
         photo_type,
         path: path.to_string(),
         file_name: photo_name,
+        capture_time: local_time.clone(),
+        local_time,
+        utc_offset,
+        gps_time,
+        camera,
+        altitude,
+        relative_altitude,
+        gimbal_yaw,
+        gimbal_pitch,
+        position_source,
     })
 
 }
 
+/// 读取EXIF中内嵌的JPEG缩略图
+pub fn exif_thumbnail(path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let mut reader = BufReader::new(&mut file);
+    let exif_data = Reader::new().read_from_container(&mut reader)?;
+
+    Ok(thumbnail_data(&exif_data).map(|v| v.to_vec()))
+}
+
+/// EXIF 中缩略图数据的位置
+pub fn thumbnail_data(exif_data: &Exif) -> Option<&[u8]> {
+    let offset = exif_data.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL).and_then(|v| v.value.get_uint(0))?;
+    let length = exif_data.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL).and_then(|v| v.value.get_uint(0))?;
+
+    let (start, end) = (offset as usize, offset as usize + length as usize);
+    exif_data.buf().get(start..end)
+}
+
 fn get_gps_info(exif_data: &Exif, tag: Tag) -> anyhow::Result<f64> {
     if let Some(field) = exif_data.get_field(tag, In::PRIMARY) {
         convert_gps_field(field, tag)
@@ -117,6 +314,48 @@ fn get_gps_info(exif_data: &Exif, tag: Tag) -> anyhow::Result<f64> {
     }
 }
 
+fn get_gps_altitude(exif_data: &Exif) -> Option<f64> {
+    let field = exif_data.get_field(Tag::GPSAltitude, In::PRIMARY)?;
+    let altitude = match field.value {
+        Value::Rational(ref vec) if !vec.is_empty() => vec[0].to_f64(),
+        _ => return None,
+    };
+
+    // GPSAltitudeRef 为 1 表示低于海平面
+    let below = exif_data.get_field(Tag::GPSAltitudeRef, In::PRIMARY).and_then(|v| v.value.get_uint(0)) == Some(1);
+
+    Some(if below { -altitude } else { altitude })
+}
+
+fn get_capture_time(exif_data: &Exif) -> Option<String> {
+    let field = exif_data.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    match field.value {
+        Value::Ascii(ref vec) if !vec.is_empty() => {
+            DateTime::from_ascii(&vec[0]).ok().map(|dt| dt.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn get_ascii(exif_data: &Exif, tag: Tag) -> Option<String> {
+    let field = exif_data.get_field(tag, In::PRIMARY)?;
+    match field.value {
+        Value::Ascii(ref vec) if !vec.is_empty() => {
+            Some(String::from_utf8_lossy(&vec[0]).trim_matches(char::from(0)).trim().to_string()).filter(|v| !v.is_empty())
+        }
+        _ => None,
+    }
+}
+
+fn get_gps_time(exif_data: &Exif) -> Option<i64> {
+    let date = get_ascii(exif_data, Tag::GPSDateStamp)?;
+    let field = exif_data.get_field(Tag::GPSTimeStamp, In::PRIMARY)?;
+    match field.value {
+        Value::Rational(ref vec) if vec.len() == 3 => gps_timestamp(&date, vec[0].to_f64(), vec[1].to_f64(), vec[2].to_f64()),
+        _ => None,
+    }
+}
+
 fn convert_gps_field(field: &Field, tag: Tag) -> anyhow::Result<f64> {
 
     let value = field.value.display_as(tag).to_string();
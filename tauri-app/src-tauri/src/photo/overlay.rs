@@ -0,0 +1,243 @@
+use std::fs;
+use std::io::Cursor;
+use anyhow::anyhow;
+use exif::{In, Reader, Tag};
+use image::{DynamicImage, Rgb, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+use imageproc::drawing::{draw_text_mut, text_size};
+use rayon::prelude::*;
+use rusttype::{Font, Scale};
+use serde::{Deserialize, Serialize};
+use crate::photo::{Photo, PhotoError};
+use crate::photo::annotate::{upright_jpeg, PhotoTag};
+use crate::photo::jpeg::keep_metadata;
+use crate::photo::privacy::{PrivacyOptions, PrivacyProfile};
+
+/// 未指定字体时依次查找的系统中文字体
+pub static DEFAULT_FONTS: [&str; 5] = [
+    "C:/Windows/Fonts/msyh.ttc",
+    "C:/Windows/Fonts/simhei.ttf",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum OverlayField {
+    Line,
+    Station,
+    AssetId,
+    /// 相机本地拍摄时间
+    CaptureTime,
+    Coordinates,
+    Altitude,
+    /// 照片到杆塔的距离
+    Distance,
+    PhotoType,
+}
+
+/// 在输出照片上叠加文字横幅, 需要重新编码图像
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OverlayOptions {
+    pub enabled: bool,
+    pub position: OverlayPosition,
+    /// 字体文件, 不指定时查找系统中文字体
+    pub font: Option<String>,
+    /// 文字高度占图片高度的比例
+    pub font_scale: f32,
+    /// 每行显示一项, 按顺序排列
+    pub fields: Vec<OverlayField>,
+    pub color: [u8; 3],
+    pub background: [u8; 3],
+    /// 背景不透明度, 0~1
+    pub background_opacity: f32,
+    /// JPEG 质量, 1~100
+    pub quality: u8,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            enabled: false,
+            position: OverlayPosition::default(),
+            font: None,
+            font_scale: 0.025,
+            fields: vec![OverlayField::Line, OverlayField::Station, OverlayField::CaptureTime, OverlayField::Coordinates],
+            color: [255, 255, 255],
+            background: [0, 0, 0],
+            background_opacity: 0.5,
+            quality: 90,
+        }
+    }
+}
+
+/// 需要叠加文字的输出照片
+#[derive(Debug, Clone)]
+pub struct OverlayJob {
+    pub photo: Photo,
+    pub output: String,
+    pub tag: PhotoTag,
+}
+
+/// 横幅中的文字, 隐私模式下不显示精确坐标和高度
+pub fn overlay_lines(photo: &Photo, tag: &PhotoTag, options: &OverlayOptions, privacy: &PrivacyOptions) -> Vec<String> {
+    options.fields.iter().filter_map(|field| {
+        let text = match field {
+            OverlayField::Line => format!("线路: {}", tag.line),
+            OverlayField::Station => format!("杆塔: {}", tag.station),
+            OverlayField::AssetId if !tag.asset_id.is_empty() => format!("资产编号: {}", tag.asset_id),
+            OverlayField::CaptureTime => format!("时间: {}", photo.local_time.as_ref().or(photo.capture_time.as_ref())?),
+            OverlayField::Coordinates => match privacy.profile {
+                PrivacyProfile::Off => format!("坐标: {:.6}, {:.6}", photo.longitude, photo.latitude),
+                PrivacyProfile::Coarse => format!("坐标: {:.*}, {:.*}", privacy.coarse_digits as usize, privacy.round(photo.longitude), privacy.coarse_digits as usize, privacy.round(photo.latitude)),
+                PrivacyProfile::Strip => return None,
+            },
+            OverlayField::Altitude if !privacy.enabled() => format!("海拔: {:.1}米", photo.altitude?),
+            OverlayField::Distance => format!("距离: {:.1}米", tag.distance?),
+            OverlayField::PhotoType => format!("类型: {}", tag.photo_type),
+            _ => return None,
+        };
+        Some(text)
+    }).collect()
+}
+
+pub fn load_font(path: Option<&str>) -> anyhow::Result<Font<'static>> {
+    let data = match path {
+        Some(path) => fs::read(path)?,
+        None => DEFAULT_FONTS.iter().find_map(|v| fs::read(v).ok()).ok_or(anyhow::Error::msg("no font found, please select a font file"))?,
+    };
+
+    Font::try_from_vec(data).ok_or(anyhow::Error::msg("invalid font file"))
+}
+
+/// 并行为照片叠加文字, 输出照片保留原有的 EXIF 和 XMP, 返回处理失败的照片
+pub fn overlay_photos(jobs: &[OverlayJob], options: &OverlayOptions, privacy: &PrivacyOptions) -> anyhow::Result<Vec<PhotoError>> {
+    let font = load_font(options.font.as_deref())?;
+
+    let failed = jobs.par_iter().filter_map(|job| {
+        let lines = overlay_lines(&job.photo, &job.tag, options, privacy);
+        overlay_photo(&job.photo.path, &job.output, &lines, &font, options).err()
+            .map(|e| PhotoError::new(&job.photo.path, e))
+    }).collect();
+
+    Ok(failed)
+}
+
+/// 先按 EXIF 方向旋转再叠加文字, 保证横幅在正常显示的画面中位置正确
+pub fn overlay_photo(path: &str, output: &str, lines: &[String], font: &Font, options: &OverlayOptions) -> anyhow::Result<()> {
+    let original = fs::read(path)?;
+    let image = image::load_from_memory(&original).map_err(|e|anyhow!(e))?;
+    let mut image = apply_orientation(image, exif_orientation(&original)).into_rgb8();
+    draw_banner(&mut image, lines, font, options);
+
+    let mut encoded = Cursor::new(vec![]);
+    JpegEncoder::new_with_quality(&mut encoded, options.quality.clamp(1, 100)).encode_image(&image).map_err(|e|anyhow!(e))?;
+
+    let bytes = keep_metadata(&original, encoded.get_ref())?;
+    fs::write(output, upright_jpeg(&bytes, image.width(), image.height())?)?;
+
+    Ok(())
+}
+
+/// EXIF 中的方向, 没有时为 1(正常)
+fn exif_orientation(bytes: &[u8]) -> u32 {
+    Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()
+        .and_then(|v| v.get_field(Tag::Orientation, In::PRIMARY).and_then(|v| v.value.get_uint(0)))
+        .unwrap_or(1)
+}
+
+/// 按 EXIF 方向(1~8)把图像转为正常显示的方向
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn draw_banner(image: &mut RgbImage, lines: &[String], font: &Font, options: &OverlayOptions) {
+    if lines.is_empty() {
+        return;
+    }
+
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let font_size = (height as f32 * options.font_scale).max(12.0);
+    let scale = Scale::uniform(font_size);
+    let line_height = (font_size * 1.3).ceil() as i32;
+    let padding = (font_size / 2.0).ceil() as i32;
+
+    let text_width = lines.iter().map(|v| text_size(scale, font, v).0).max().unwrap_or_default();
+    let banner_width = (text_width + padding * 2).min(width);
+    let banner_height = (line_height * lines.len() as i32 + padding * 2).min(height);
+
+    let x = match options.position {
+        OverlayPosition::TopLeft | OverlayPosition::BottomLeft => 0,
+        OverlayPosition::TopRight | OverlayPosition::BottomRight => width - banner_width,
+    };
+    let y = match options.position {
+        OverlayPosition::TopLeft | OverlayPosition::TopRight => 0,
+        OverlayPosition::BottomLeft | OverlayPosition::BottomRight => height - banner_height,
+    };
+
+    // 半透明背景
+    let opacity = options.background_opacity.clamp(0.0, 1.0);
+    for py in y..y + banner_height {
+        for px in x..x + banner_width {
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for (c, b) in pixel.0.iter_mut().zip(options.background.iter()) {
+                *c = (*c as f32 * (1.0 - opacity) + *b as f32 * opacity).round() as u8;
+            }
+        }
+    }
+
+    for (idx, line) in lines.iter().enumerate() {
+        draw_text_mut(image, Rgb(options.color), x + padding, y + padding + line_height * idx as i32, scale, font, line);
+    }
+}
+
+#[test]
+fn test_overlay_lines() {
+    let photo = Photo {
+        longitude: 120.6543214,
+        latitude: 30.1234567,
+        local_time: Some("2023-06-01 10:00:00".to_string()),
+        altitude: Some(150.52),
+        ..Default::default()
+    };
+    let tag = PhotoTag {
+        line: "茶园线".to_string(),
+        station: "#12".to_string(),
+        asset_id: String::new(),
+        distance: Some(8.26),
+        photo_type: "红外".to_string(),
+    };
+    let options = OverlayOptions {
+        fields: vec![OverlayField::Station, OverlayField::AssetId, OverlayField::CaptureTime, OverlayField::Coordinates, OverlayField::Altitude, OverlayField::Distance],
+        ..Default::default()
+    };
+
+    let lines = overlay_lines(&photo, &tag, &options, &PrivacyOptions::default());
+    assert_eq!(lines, vec!["杆塔: #12", "时间: 2023-06-01 10:00:00", "坐标: 120.654321, 30.123457", "海拔: 150.5米", "距离: 8.3米"]);
+
+    let coarse = PrivacyOptions { profile: PrivacyProfile::Coarse, ..Default::default() };
+    let lines = overlay_lines(&photo, &tag, &options, &coarse);
+    assert_eq!(lines, vec!["杆塔: #12", "时间: 2023-06-01 10:00:00", "坐标: 120.65, 30.12", "距离: 8.3米"]);
+
+    let strip = PrivacyOptions { profile: PrivacyProfile::Strip, ..Default::default() };
+    assert_eq!(overlay_lines(&photo, &tag, &options, &strip).len(), 3);
+}
@@ -0,0 +1,261 @@
+use std::fs;
+use std::path::Path;
+use exif::{Context, Field, Rational, Tag, Value};
+use serde::{Deserialize, Serialize};
+use crate::photo::annotate::PhotoTag;
+
+/// 隐私模式下归属信息的文件名
+pub static SIDECAR_FILE_NAME: &str = "照片归属.csv";
+
+/// 可识别设备或机主的字段
+pub static SERIAL_TAGS: [Tag; 5] = [Tag::MakerNote, Tag::BodySerialNumber, Tag::LensSerialNumber, Tag::CameraOwnerName, Tag::ImageUniqueID];
+
+/// 粗化坐标时保留的 GPS 字段
+pub static COARSE_GPS_TAGS: [Tag; 7] = [Tag::GPSVersionID, Tag::GPSLatitudeRef, Tag::GPSLatitude, Tag::GPSLongitudeRef, Tag::GPSLongitude, Tag::GPSDateStamp, Tag::GPSTimeStamp];
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum PrivacyProfile {
+    /// 保留原始信息
+    #[default]
+    Off,
+    /// 坐标只保留到 coarse_digits 位小数, 删除高度、速度等其他定位信息
+    Coarse,
+    /// 删除全部定位信息
+    Strip,
+}
+
+/// 照片交给外部单位前对位置和设备信息的处理, 只改写元数据, 不重新编码图像
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PrivacyOptions {
+    pub profile: PrivacyProfile,
+    /// 粗化后坐标的小数位数, 2 位约 1 公里
+    pub coarse_digits: u32,
+}
+
+impl Default for PrivacyOptions {
+    fn default() -> Self {
+        PrivacyOptions {
+            profile: PrivacyProfile::default(),
+            coarse_digits: 2,
+        }
+    }
+}
+
+impl PrivacyOptions {
+    pub fn enabled(&self) -> bool {
+        self.profile != PrivacyProfile::Off
+    }
+
+    /// 按 coarse_digits 四舍五入坐标
+    pub fn round(&self, value: f64) -> f64 {
+        let scale = 10f64.powi(self.coarse_digits as i32);
+        (value * scale).round() / scale
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum XmpAction {
+    Keep,
+    Round,
+    Remove,
+}
+
+/// 按隐私选项处理 EXIF 字段, 返回 None 时删除该字段
+pub fn privacy_field(field: &Field, options: &PrivacyOptions) -> Option<Field> {
+    if !options.enabled() {
+        return Some(field.clone());
+    }
+    if SERIAL_TAGS.contains(&field.tag) {
+        return None;
+    }
+    if field.tag.context() != Context::Gps {
+        return Some(field.clone());
+    }
+    if options.profile == PrivacyProfile::Strip || !COARSE_GPS_TAGS.contains(&field.tag) {
+        return None;
+    }
+
+    match (field.tag, &field.value) {
+        (Tag::GPSLatitude | Tag::GPSLongitude, Value::Rational(v)) if v.len() == 3 => {
+            let degrees = v[0].to_f64() + v[1].to_f64() / 60.0 + v[2].to_f64() / 3600.0;
+            Some(Field { value: Value::Rational(dms(options.round(degrees))), ..field.clone() })
+        }
+        (Tag::GPSLatitude | Tag::GPSLongitude, _) => None,
+        _ => Some(field.clone()),
+    }
+}
+
+/// 十进制度数转为 度/分/秒, 分保留 4 位小数
+fn dms(degrees: f64) -> Vec<Rational> {
+    let whole = degrees.trunc();
+    let minutes = ((degrees - whole) * 60.0 * 10000.0).round() as u32;
+
+    vec![Rational::from((whole as u32, 1)), Rational::from((minutes, 10000)), Rational::from((0, 1))]
+}
+
+/// 大疆 XMP 中的坐标、高度、RTK 和序列号
+fn xmp_action(name: &str) -> XmpAction {
+    let local = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+    if name.starts_with("xmlns") {
+        XmpAction::Keep
+    } else if ["latitude", "longitude", "longtitude"].iter().any(|v| local.contains(v)) && !local.contains("ref") {
+        XmpAction::Round
+    } else if ["gps", "altitude", "rtk", "serial"].iter().any(|v| local.contains(v)) {
+        XmpAction::Remove
+    } else {
+        XmpAction::Keep
+    }
+}
+
+/// 按隐私选项处理 XMP 中属性和元素形式的字段
+pub fn privacy_xmp(xmp: &str, options: &PrivacyOptions) -> String {
+    if !options.enabled() {
+        return xmp.to_string();
+    }
+
+    // 粗化时只保留能解析为数字的坐标
+    let replace = |name: &str, value: &str| -> Option<String> {
+        match xmp_action(name) {
+            XmpAction::Keep => Some(value.to_string()),
+            XmpAction::Round if options.profile == PrivacyProfile::Coarse => {
+                value.trim().parse::<f64>().ok().map(|v| format!("{:.*}", options.coarse_digits as usize, options.round(v)))
+            }
+            _ => None,
+        }
+    };
+
+    let xmp = rewrite_attributes(xmp, &replace);
+    rewrite_elements(&xmp, &replace)
+}
+
+/// 改写 name="value" 形式的属性, replace 返回 None 时删除该属性
+fn rewrite_attributes(xmp: &str, replace: &impl Fn(&str, &str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(xmp.len());
+    let mut rest = xmp;
+
+    while let Some(idx) = rest.find("=\"") {
+        let value_start = idx + 2;
+        let value_end = match rest[value_start..].find('"') {
+            Some(len) => value_start + len,
+            None => break,
+        };
+        let name_start = rest[..idx].rfind(|c: char| c.is_whitespace() || c == '<').map_or(0, |v| v + 1);
+        let name = &rest[name_start..idx];
+
+        match replace(name, &rest[value_start..value_end]) {
+            Some(value) => {
+                result.push_str(&rest[..value_start]);
+                result.push_str(value.as_str());
+                result.push('"');
+            }
+            None => result.push_str(rest[..name_start].trim_end()),
+        }
+        rest = &rest[value_end + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// 改写 <name>value</name> 形式的元素, replace 返回 None 时删除该元素
+fn rewrite_elements(xmp: &str, replace: &impl Fn(&str, &str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(xmp.len());
+    let mut rest = xmp;
+
+    while let Some(idx) = rest.find("</") {
+        let name_end = match rest[idx..].find('>') {
+            Some(len) => idx + len,
+            None => break,
+        };
+        let name = &rest[idx + 2..name_end];
+        let open = format!("<{}>", name);
+        let value_start = rest[..idx].rfind(open.as_str()).map(|v| v + open.len());
+
+        match value_start {
+            // 只处理不含子元素的元素
+            Some(value_start) if !rest[value_start..idx].contains('<') => {
+                match replace(name, &rest[value_start..idx]) {
+                    Some(value) => {
+                        result.push_str(&rest[..value_start]);
+                        result.push_str(value.as_str());
+                        result.push_str(&rest[idx..=name_end]);
+                    }
+                    None => result.push_str(&rest[..value_start - open.len()]),
+                }
+            }
+            _ => result.push_str(&rest[..=name_end]),
+        }
+        rest = &rest[name_end + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// 隐私模式下坐标被删除, 归属信息另写到目录中的 CSV, 含逗号、引号的字段加引号
+pub fn write_sidecar(folder: &Path, tags: &[(String, PhotoTag)]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(folder.join(SIDECAR_FILE_NAME))?;
+    writer.write_record(["照片", "线路", "杆塔", "资产编号", "距离(米)", "类型"])?;
+    for (file_name, tag) in tags.iter() {
+        let distance = tag.distance.map(|v| format!("{:.1}", v)).unwrap_or_default();
+        writer.write_record([file_name, &tag.line, &tag.station, &tag.asset_id, &distance, &tag.photo_type])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_privacy() {
+    use exif::In;
+
+    let coarse = PrivacyOptions { profile: PrivacyProfile::Coarse, ..Default::default() };
+    let strip = PrivacyOptions { profile: PrivacyProfile::Strip, ..Default::default() };
+
+    let latitude = Field {
+        tag: Tag::GPSLatitude,
+        ifd_num: In::PRIMARY,
+        value: Value::Rational(vec![Rational::from((30, 1)), Rational::from((7, 1)), Rational::from((2448, 100))]),
+    };
+    let rounded = privacy_field(&latitude, &coarse).unwrap();
+    match rounded.value {
+        Value::Rational(v) => assert_eq!((v[0].to_f64(), v[1].to_f64(), v[2].to_f64()), (30.0, 7.2, 0.0)),
+        _ => panic!("latitude should be rational"),
+    }
+    assert!(privacy_field(&latitude, &strip).is_none());
+    assert!(privacy_field(&latitude, &PrivacyOptions::default()).is_some());
+
+    let altitude = Field { tag: Tag::GPSAltitude, ifd_num: In::PRIMARY, value: Value::Rational(vec![Rational::from((1505, 10))]) };
+    assert!(privacy_field(&altitude, &coarse).is_none());
+    let serial = Field { tag: Tag::BodySerialNumber, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"1ZNBJ7R0010155".to_vec()]) };
+    assert!(privacy_field(&serial, &coarse).is_none());
+    let model = Field { tag: Tag::Model, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"ZH20T".to_vec()]) };
+    assert!(privacy_field(&model, &strip).is_some());
+
+    let xmp = r#"<rdf:Description xmlns:drone-dji="http://www.dji.com/drone-dji/1.0/" drone-dji:GpsLatitude="+30.123456"
+        drone-dji:GpsLongtitude="+120.654321" drone-dji:AbsoluteAltitude="+150.50" drone-dji:GimbalYawDegree="-35.6">
+        <drone-dji:DroneSerialNumber>1ZNBJ7R0010155</drone-dji:DroneSerialNumber><tower:Station>#12</tower:Station></rdf:Description>"#;
+
+    let result = privacy_xmp(xmp, &coarse);
+    assert!(result.contains(r#"drone-dji:GpsLatitude="30.12""#));
+    assert!(result.contains(r#"drone-dji:GpsLongtitude="120.65""#));
+    assert!(result.contains(r#"xmlns:drone-dji="http://www.dji.com/drone-dji/1.0/""#));
+    assert!(result.contains(r#"drone-dji:GimbalYawDegree="-35.6">"#));
+    assert!(!result.contains("AbsoluteAltitude"));
+    assert!(!result.contains("SerialNumber"));
+    assert!(result.contains("<tower:Station>#12</tower:Station>"));
+
+    let result = privacy_xmp(xmp, &strip);
+    assert!(!result.contains("GpsLatitude"));
+    assert!(!result.contains("GpsLongtitude"));
+    assert!(result.contains(r#"drone-dji:GimbalYawDegree="-35.6">"#));
+
+    let folder = std::env::temp_dir().join("test_privacy_sidecar");
+    fs::create_dir_all(&folder).unwrap();
+    let tag = PhotoTag { line: "茶园线".to_string(), station: "#12,\"A\"".to_string(), distance: Some(8.26), ..Default::default() };
+    write_sidecar(&folder, &[("DJI_0001.JPG".to_string(), tag)]).unwrap();
+    let text = fs::read_to_string(folder.join(SIDECAR_FILE_NAME)).unwrap();
+    assert_eq!(text.lines().nth(1), Some("DJI_0001.JPG,茶园线,\"#12,\"\"A\"\"\",,8.3,"));
+    fs::remove_dir_all(&folder).unwrap();
+}
@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::Path;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use crate::photo::clock::TIME_FORMAT;
+use crate::utils::file_name;
+
+/// 与字幕文件同名的视频扩展名
+pub static VIDEO_EXTENSIONS: [&str; 2] = ["mp4", "mov"];
+
+/// 大疆视频字幕中每帧的飞行数据
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SrtFrame {
+    /// 相对视频开头的时间(秒)
+    pub offset: f64,
+    /// 相机本地时间, 格式 "YYYY-MM-DD HH:MM:SS"
+    pub time: Option<String>,
+    pub longitude: f64,
+    pub latitude: f64,
+    /// 海拔(米)
+    pub altitude: Option<f64>,
+    /// 相对起飞点高度(米)
+    pub relative_altitude: Option<f64>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Video {
+    /// 视频文件, 找不到与字幕同名的视频时为 None
+    pub path: Option<String>,
+    pub srt_path: String,
+    pub file_name: String,
+    pub frames: Vec<SrtFrame>,
+}
+
+/// 读取目录下的大疆 SRT 字幕及同名视频
+pub fn video_list(path: &str) -> anyhow::Result<Vec<Video>> {
+    let mut videos = vec![];
+
+    for entry in fs::read_dir(path)? {
+        let srt_path = entry?.path();
+        let is_srt = srt_path.extension().and_then(|v| v.to_str()).is_some_and(|v| v.eq_ignore_ascii_case("srt"));
+        if !srt_path.is_file() || !is_srt {
+            continue;
+        }
+
+        let text = fs::read_to_string(&srt_path)?;
+        let frames = parse_srt(&text);
+        if frames.is_empty() {
+            continue;
+        }
+
+        let srt = srt_path.to_string_lossy().to_string();
+        let video = find_video(&srt_path);
+        let name = match video.as_ref() {
+            Some(video) => Path::new(video).file_name().map(|v| v.to_string_lossy().to_string()).unwrap_or_default(),
+            None => file_name(&srt)?,
+        };
+
+        videos.push(Video { path: video, srt_path: srt, file_name: name, frames });
+    }
+    videos.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok(videos)
+}
+
+fn find_video(srt_path: &Path) -> Option<String> {
+    let dir = srt_path.parent()?;
+    let stem = srt_path.file_stem()?.to_str()?;
+
+    fs::read_dir(dir).ok()?.filter_map(|v| v.ok()).map(|v| v.path()).find(|v| {
+        let same_stem = v.file_stem().and_then(|v| v.to_str()) == Some(stem);
+        let is_video = v.extension().and_then(|v| v.to_str()).is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|v| ext.eq_ignore_ascii_case(v)));
+        same_stem && is_video
+    }).map(|v| v.to_string_lossy().to_string())
+}
+
+/// 解析 SRT 字幕, 兼容 "[latitude: 30.1] [longitude: 120.1]" 和 "GPS(120.1,30.1,15)" 两种写法, 没有坐标的帧忽略
+pub fn parse_srt(text: &str) -> Vec<SrtFrame> {
+    let text = text.replace("\r\n", "\n");
+    let mut frames = vec![];
+
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().map(|v| v.trim()).filter(|v| !v.is_empty());
+        let _index = lines.next();
+        let offset = match lines.next().and_then(|v| v.split("-->").next()).and_then(parse_srt_offset) {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let content: Vec<&str> = lines.collect();
+        let content = content.join("\n");
+
+        let gps = gps_values(&content);
+        let longitude = number_after(&content, &["longitude", "longtitude"]).or(gps.first().cloned());
+        let latitude = number_after(&content, &["latitude"]).or(gps.get(1).cloned());
+        let (longitude, latitude) = match (longitude, latitude) {
+            (Some(longitude), Some(latitude)) if longitude != 0.0 && latitude != 0.0 => (longitude, latitude),
+            _ => continue,
+        };
+
+        frames.push(SrtFrame {
+            offset,
+            time: content.lines().find_map(parse_frame_time),
+            longitude,
+            latitude,
+            altitude: number_after(&content, &["abs_alt", "altitude"]).or(gps.get(2).cloned()),
+            relative_altitude: number_after(&content, &["rel_alt"]),
+        });
+    }
+
+    frames
+}
+
+/// 解析 "00:01:02,500" 为秒数
+fn parse_srt_offset(text: &str) -> Option<f64> {
+    let (time, millis) = text.trim().split_once(',')?;
+    let parts: Vec<f64> = time.split(':').map(|v| v.parse::<f64>()).collect::<Result<_, _>>().ok()?;
+    if parts.len() != 3 {
+        return None;
+    }
+
+    Some(parts[0] * 3600.0 + parts[1] * 60.0 + parts[2] + millis.parse::<f64>().ok()? / 1000.0)
+}
+
+/// 在一行中查找日期时间, 如 "2023-06-01 10:00:00.123"、"HOME(...) 2017.08.05 14:11:51"
+fn parse_frame_time(line: &str) -> Option<String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let formats = ["%Y-%m-%d %H:%M:%S%.f", "%Y.%m.%d %H:%M:%S%.f", "%Y/%m/%d %H:%M:%S%.f"];
+
+    tokens.windows(2).find_map(|v| {
+        // 部分机型的时间写作 "10:00:00,123,456"
+        let text = format!("{} {}", v[0], v[1].split(',').next().unwrap_or_default());
+        formats.iter().find_map(|f| NaiveDateTime::parse_from_str(&text, f).ok())
+    }).map(|v| v.format(TIME_FORMAT).to_string())
+}
+
+/// 取 key 之后的第一个数字
+fn number_after(text: &str, keys: &[&str]) -> Option<f64> {
+    keys.iter().find_map(|key| {
+        let rest = &text[text.find(key)? + key.len()..];
+        let start = rest.find(|c: char| c.is_ascii_digit() || c == '-')?;
+        let number: String = rest[start..].chars().enumerate()
+            .take_while(|(idx, c)| c.is_ascii_digit() || *c == '.' || (*idx == 0 && *c == '-'))
+            .map(|(_, c)| c)
+            .collect();
+        number.parse().ok()
+    })
+}
+
+/// "GPS(经度,纬度,高度)" 中的数值
+fn gps_values(text: &str) -> Vec<f64> {
+    let start = match text.find("GPS(") {
+        Some(start) => start + 4,
+        None => return vec![],
+    };
+    let end = text[start..].find(')').map_or(text.len(), |v| start + v);
+
+    text[start..end].split(',').filter_map(|v| v.trim().parse().ok()).collect()
+}
+
+#[test]
+fn test_parse_srt() {
+    let srt = "1\r\n00:00:00,000 --> 00:00:00,033\r\n<font size=\"28\">FrameCnt: 1, DiffTime: 33ms\r\n2023-06-01 10:00:00.123\r\n[iso: 100] [latitude: 30.123456] [longitude: 120.654321] [rel_alt: 50.000 abs_alt: 150.500] </font>\r\n\r\n\
+        2\r\n00:00:01,500 --> 00:00:01,533\r\n<font size=\"28\">FrameCnt: 2\r\n2023-06-01 10:00:01.623\r\n[latitude: 0.000000] [longitude: 0.000000]</font>\r\n\r\n\
+        3\r\n00:01:02,000 --> 00:01:03,000\r\nHOME(120.0,30.0) 2017.08.05 14:11:51\r\nGPS(120.1234,30.5678,15) BAROMETER:12.3\r\n";
+
+    let frames = parse_srt(srt);
+    assert_eq!(frames.len(), 2);
+
+    assert_eq!(frames[0].offset, 0.0);
+    assert_eq!(frames[0].time.as_deref(), Some("2023-06-01 10:00:00"));
+    assert_eq!(frames[0].latitude, 30.123456);
+    assert_eq!(frames[0].longitude, 120.654321);
+    assert_eq!(frames[0].altitude, Some(150.5));
+    assert_eq!(frames[0].relative_altitude, Some(50.0));
+
+    assert_eq!(frames[1].offset, 62.0);
+    assert_eq!(frames[1].time.as_deref(), Some("2017-08-05 14:11:51"));
+    assert_eq!(frames[1].longitude, 120.1234);
+    assert_eq!(frames[1].latitude, 30.5678);
+    assert_eq!(frames[1].altitude, Some(15.0));
+}
@@ -0,0 +1,236 @@
+use std::ffi::c_void;
+use std::fs;
+use std::path::PathBuf;
+use anyhow::anyhow;
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use crate::photo::jpeg::{find_app3, read_header_segments};
+
+/// 大疆红外测温 SDK(DJI Thermal SDK)的动态库, 放在程序所在目录或在参数中指定路径
+///
+/// R-JPEG 中 APP3 段的原始数据与温度的换算关系未公开且随机型变化, 测温统一交给 SDK 的 dirp_measure_ex
+#[cfg(windows)]
+pub static DIRP_LIBRARY: &str = "libdirp.dll";
+#[cfg(not(windows))]
+pub static DIRP_LIBRARY: &str = "libdirp.so";
+
+/// 测温参数, 与大疆红外相机中的测温参数含义相同, 不指定时使用照片中记录的参数
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ThermalParams {
+    /// 发射率, 0.1~1
+    pub emissivity: Option<f64>,
+    /// 目标距离(米), 1~25
+    pub distance: Option<f64>,
+    /// 相对湿度(%), 20~100
+    pub humidity: Option<f64>,
+    /// 反射温度(℃)
+    pub reflected: Option<f64>,
+}
+
+impl ThermalParams {
+    /// 用指定的参数覆盖照片中记录的参数
+    fn apply(&self, params: &mut DirpParams) {
+        let values = [
+            (self.distance, &mut params.distance),
+            (self.humidity, &mut params.humidity),
+            (self.emissivity, &mut params.emissivity),
+            (self.reflected, &mut params.reflection),
+        ];
+        for (value, target) in values {
+            if let Some(value) = value {
+                *target = value as f32;
+            }
+        }
+    }
+}
+
+/// SDK 的 dirp_measurement_params_t, 新版 SDK 在末尾增加了字段, 预留空间避免越界写入
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct DirpParams {
+    distance: f32,
+    humidity: f32,
+    emissivity: f32,
+    reflection: f32,
+    reserved: [f32; 4],
+}
+
+impl From<DirpParams> for ThermalParams {
+    fn from(params: DirpParams) -> Self {
+        ThermalParams {
+            emissivity: Some(params.emissivity as f64),
+            distance: Some(params.distance as f64),
+            humidity: Some(params.humidity as f64),
+            reflected: Some(params.reflection as f64),
+        }
+    }
+}
+
+/// SDK 的 dirp_resolution_t
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+struct DirpResolution {
+    width: i32,
+    height: i32,
+}
+
+type CreateFn = unsafe extern "C" fn(*const u8, i32, *mut *mut c_void) -> i32;
+type DestroyFn = unsafe extern "C" fn(*mut c_void) -> i32;
+type ResolutionFn = unsafe extern "C" fn(*mut c_void, *mut DirpResolution) -> i32;
+type GetParamsFn = unsafe extern "C" fn(*mut c_void, *mut DirpParams) -> i32;
+type SetParamsFn = unsafe extern "C" fn(*mut c_void, *const DirpParams) -> i32;
+type MeasureFn = unsafe extern "C" fn(*mut c_void, *mut f32, i32) -> i32;
+
+/// 已加载的测温 SDK
+pub struct ThermalSdk {
+    library: Library,
+}
+
+impl ThermalSdk {
+    /// 不指定路径时依次查找程序所在目录和系统库路径
+    pub fn load(path: Option<&str>) -> anyhow::Result<Self> {
+        let candidates: Vec<PathBuf> = match path {
+            Some(path) => vec![PathBuf::from(path)],
+            None => std::env::current_exe().ok()
+                .and_then(|v| v.parent().map(|v| v.join(DIRP_LIBRARY)))
+                .into_iter()
+                .chain([PathBuf::from(DIRP_LIBRARY)])
+                .collect(),
+        };
+
+        let mut error = String::new();
+        for candidate in candidates.iter() {
+            match unsafe { Library::new(candidate) } {
+                Ok(library) => return Ok(ThermalSdk { library }),
+                Err(e) => error = e.to_string(),
+            }
+        }
+
+        Err(anyhow::Error::msg(format!("DJI Thermal SDK ({}) not found: {}", DIRP_LIBRARY, error)))
+    }
+
+    fn function<T: Copy>(&self, name: &[u8]) -> anyhow::Result<T> {
+        let symbol = unsafe { self.library.get::<T>(name) }.map_err(|e|anyhow!(e))?;
+        Ok(*symbol)
+    }
+
+    /// 计算 R-JPEG 的温度矩阵, 返回温度矩阵和实际使用的测温参数
+    pub fn measure(&self, data: &[u8], params: &ThermalParams) -> anyhow::Result<(ThermalImage, ThermalParams)> {
+        let create: CreateFn = self.function(b"dirp_create_from_rjpeg\0")?;
+        let destroy: DestroyFn = self.function(b"dirp_destroy\0")?;
+
+        let mut handle = std::ptr::null_mut();
+        check(unsafe { create(data.as_ptr(), data.len() as i32, &mut handle) }, "dirp_create_from_rjpeg")?;
+        let result = self.measure_handle(handle, params);
+        unsafe { destroy(handle) };
+
+        result
+    }
+
+    fn measure_handle(&self, handle: *mut c_void, params: &ThermalParams) -> anyhow::Result<(ThermalImage, ThermalParams)> {
+        let resolution: ResolutionFn = self.function(b"dirp_get_rjpeg_resolution\0")?;
+        let get_params: GetParamsFn = self.function(b"dirp_get_measurement_params\0")?;
+        let set_params: SetParamsFn = self.function(b"dirp_set_measurement_params\0")?;
+        let measure: MeasureFn = self.function(b"dirp_measure_ex\0")?;
+
+        let mut size = DirpResolution::default();
+        check(unsafe { resolution(handle, &mut size) }, "dirp_get_rjpeg_resolution")?;
+        let (width, height) = (size.width.max(0) as usize, size.height.max(0) as usize);
+
+        // 照片中记录的参数作为默认值
+        let mut dirp_params = DirpParams::default();
+        check(unsafe { get_params(handle, &mut dirp_params) }, "dirp_get_measurement_params")?;
+        params.apply(&mut dirp_params);
+        check(unsafe { set_params(handle, &dirp_params) }, "dirp_set_measurement_params")?;
+
+        let mut data = vec![0f32; width * height];
+        let bytes = (data.len() * std::mem::size_of::<f32>()) as i32;
+        check(unsafe { measure(handle, data.as_mut_ptr(), bytes) }, "dirp_measure_ex")?;
+
+        let image = ThermalImage { width, height, data: data.into_iter().map(|v| v as f64).collect() };
+        Ok((image, dirp_params.into()))
+    }
+}
+
+fn check(code: i32, name: &str) -> anyhow::Result<()> {
+    if code != 0 {
+        return Err(anyhow::Error::msg(format!("{} failed: {}", name, code)));
+    }
+
+    Ok(())
+}
+
+/// 温度矩阵(℃), 按行存储
+#[derive(Default, Debug, Clone)]
+pub struct ThermalImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f64>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ThermalStats {
+    pub max: f64,
+    pub min: f64,
+    pub mean: f64,
+    /// 温度中位数
+    pub median: f64,
+    /// 最高温点的像素位置 [x, y]
+    pub hotspot: [usize; 2],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ThermalImage {
+    pub fn stats(&self) -> ThermalStats {
+        let (idx, max) = self.data.iter().cloned().enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or_default();
+        let min = self.data.iter().cloned().min_by(f64::total_cmp).unwrap_or_default();
+        let mean = self.data.iter().sum::<f64>() / self.data.len().max(1) as f64;
+        let mut sorted = self.data.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted.get(sorted.len() / 2).cloned().unwrap_or_default();
+
+        ThermalStats {
+            max,
+            min,
+            mean,
+            median,
+            hotspot: [idx % self.width.max(1), idx / self.width.max(1)],
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// 读取红外照片中的温度矩阵和实际使用的测温参数, 不是辐射 JPEG(没有 APP3 数据)时返回 None
+pub fn read_thermal(path: &str, params: &ThermalParams, sdk: &ThermalSdk) -> anyhow::Result<Option<(ThermalImage, ThermalParams)>> {
+    let bytes = fs::read(path)?;
+    let segments = read_header_segments(&mut bytes.as_slice())?;
+    if find_app3(&segments).is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(sdk.measure(&bytes, params)?))
+}
+
+#[test]
+fn test_thermal_params() {
+    let image = ThermalImage { width: 4, height: 2, data: vec![20.0, 25.0, 30.0, 20.0, 20.0, 80.0, 20.0, 20.0] };
+    let stats = image.stats();
+    assert_eq!(stats.max, 80.0);
+    assert_eq!(stats.min, 20.0);
+    assert_eq!(stats.hotspot, [1, 1]);
+    assert_eq!(stats.median, 20.0);
+
+    // 只覆盖指定的参数, 其余沿用照片中的参数
+    let mut params = DirpParams { distance: 5.0, humidity: 70.0, emissivity: 1.0, reflection: 23.0, ..Default::default() };
+    ThermalParams { emissivity: Some(0.95), distance: Some(10.0), ..Default::default() }.apply(&mut params);
+    let used = ThermalParams::from(params);
+    assert_eq!(used.distance, Some(10.0));
+    assert_eq!(used.humidity, Some(70.0));
+    assert_eq!(used.reflected, Some(23.0));
+    assert!((used.emissivity.unwrap() - 0.95).abs() < 1e-6);
+}
@@ -0,0 +1,245 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::UNIX_EPOCH;
+use anyhow::anyhow;
+use exif::Reader;
+use image::codecs::jpeg::JpegEncoder;
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Manager, Runtime};
+use tauri::http::{Request, Response, ResponseBuilder};
+use crate::photo::thumbnail_data;
+use crate::photo::jpeg::{frame_size, split_jpeg};
+
+/// 缩略图的 URI scheme, 前端用 convertFileSrc(path, "thumb") 生成地址, 可加 "?size=320" 指定长边
+///
+/// 需要解码原图时先返回内嵌缩略图(没有时返回 503), 生成后发送 THUMBNAIL_EVENT, 前端收到后重新加载
+pub static THUMBNAIL_SCHEME: &str = "thumb";
+
+/// 缩略图生成完成的事件, 内容为请求中的照片路径
+pub static THUMBNAIL_EVENT: &str = "thumbnail-ready";
+
+/// 允许生成缩略图的目录, 设置照片目录和输出目录时同步更新
+///
+/// 协议处理函数在 UI 线程中同步调用, 不能等待异步运行时中的锁, 因此另存一份
+pub static THUMBNAIL_ROOTS: Lazy<RwLock<ThumbnailRoots>> = Lazy::new(|| {
+    RwLock::new(ThumbnailRoots::default())
+});
+
+/// 正在后台生成的缩略图缓存文件, 避免重复生成
+static PENDING: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| {
+    Mutex::new(HashSet::new())
+});
+
+#[derive(Default, Debug, Clone)]
+pub struct ThumbnailRoots {
+    pub photos: String,
+    pub output: String,
+}
+
+/// 默认缩略图长边(像素), 与大疆内嵌缩略图相同, 可直接使用内嵌缩略图
+pub static THUMBNAIL_SIZE: u32 = 160;
+
+pub static MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+/// 缓存目录, 位于应用缓存目录下
+pub static THUMBNAIL_DIR: &str = "thumbnails";
+
+/// 处理缩略图请求, 只提供照片目录和输出目录下的 JPEG 文件
+pub fn thumbnail_protocol<R: Runtime>(app: &AppHandle<R>, request: &Request) -> Result<Response, Box<dyn std::error::Error>> {
+    let (request_path, size) = match parse_request(request.uri()) {
+        Some(v) => v,
+        None => return ResponseBuilder::new().status(400).body(vec![]),
+    };
+    let path = match allowed_path(&request_path) {
+        Some(path) => path,
+        None => return ResponseBuilder::new().status(403).body(vec![]),
+    };
+
+    let cache_dir = app.path_resolver().app_cache_dir().unwrap_or(std::env::temp_dir()).join(THUMBNAIL_DIR);
+    let result = cached_thumbnail(&path, size, &cache_dir).map(|(cache_file, cached)| {
+        // 解码和缩放较慢, 放到后台线程, 先返回较小的内嵌缩略图
+        if cached.is_none() {
+            spawn_thumbnail(app.clone(), request_path, path.clone(), size, cache_file);
+        }
+        cached
+    });
+
+    match result {
+        Ok(Some(data)) => ResponseBuilder::new().mimetype("image/jpeg").header("Cache-Control", "max-age=3600").body(data),
+        Ok(None) => match fs::read(&path).ok().and_then(|v| embedded_thumbnail(&v, 0)) {
+            Some(data) => ResponseBuilder::new().mimetype("image/jpeg").header("Cache-Control", "no-store").body(data),
+            None => ResponseBuilder::new().status(503).header("Retry-After", "1").body(vec![]),
+        },
+        Err(e) => ResponseBuilder::new().status(500).mimetype("text/plain").body(e.to_string().into_bytes()),
+    }
+}
+
+fn spawn_thumbnail<R: Runtime>(app: AppHandle<R>, request_path: String, path: PathBuf, size: u32, cache_file: PathBuf) {
+    let added = PENDING.lock().is_ok_and(|mut pending| pending.insert(cache_file.clone()));
+    if !added {
+        return;
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = resize_file(&path, size, &cache_file);
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.remove(&cache_file);
+        }
+        if result.is_ok() {
+            let _ = app.emit_all(THUMBNAIL_EVENT, request_path);
+        }
+    });
+}
+
+/// 解析 "thumb://localhost/<编码后的路径>?size=320" 或 Windows 上的 "https://thumb.localhost/<编码后的路径>"
+pub fn parse_request(uri: &str) -> Option<(String, u32)> {
+    let rest = &uri[uri.find("://")? + 3..];
+    let rest = &rest[rest.find('/')? + 1..];
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let size = query.split('&')
+        .find_map(|v| v.strip_prefix("size="))
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(THUMBNAIL_SIZE)
+        .clamp(16, MAX_THUMBNAIL_SIZE);
+    let path = percent_decode(path)?;
+    if path.is_empty() {
+        return None;
+    }
+
+    Some((path, size))
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(idx + 1..idx + 3)?).ok()?;
+            result.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            result.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+
+    String::from_utf8(result).ok()
+}
+
+/// 解析符号链接和 ".." 后仍位于照片目录或输出目录下的 JPEG 文件
+fn allowed_path(path: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    let is_jpeg = path.extension().and_then(|v| v.to_str()).is_some_and(|v| v.eq_ignore_ascii_case("jpg") || v.eq_ignore_ascii_case("jpeg"));
+    if !path.is_file() || !is_jpeg {
+        return None;
+    }
+
+    let roots = match THUMBNAIL_ROOTS.read() {
+        Ok(roots) => [roots.photos.clone(), roots.output.clone()],
+        Err(_) => return None,
+    };
+    roots.iter()
+        .filter(|v| !v.is_empty())
+        .filter_map(|v| fs::canonicalize(v).ok())
+        .any(|root| path.starts_with(root))
+        .then_some(path)
+}
+
+/// 读取缓存或足够大的内嵌缩略图, 不解码原图, 都没有时返回 None 和应写入的缓存文件, 照片修改后缓存自动失效
+pub fn cached_thumbnail(path: &Path, size: u32, cache_dir: &Path) -> anyhow::Result<(PathBuf, Option<Vec<u8>>)> {
+    let modified = fs::metadata(path)?.modified()?.duration_since(UNIX_EPOCH).map_err(|e|anyhow!(e))?;
+    let cache_file = cache_dir.join(cache_name(path, modified.as_millis(), size));
+    if let Ok(data) = fs::read(&cache_file) {
+        return Ok((cache_file, Some(data)));
+    }
+
+    let data = match embedded_thumbnail(&fs::read(path)?, size) {
+        Some(data) => data,
+        None => return Ok((cache_file, None)),
+    };
+    fs::create_dir_all(cache_dir)?;
+    fs::write(&cache_file, &data)?;
+
+    Ok((cache_file, Some(data)))
+}
+
+/// 解码原图生成缩略图并写入缓存
+fn resize_file(path: &Path, size: u32, cache_file: &Path) -> anyhow::Result<()> {
+    let data = resize(&fs::read(path)?, size)?;
+    if let Some(cache_dir) = cache_file.parent() {
+        fs::create_dir_all(cache_dir)?;
+    }
+    fs::write(cache_file, data)?;
+
+    Ok(())
+}
+
+fn cache_name(path: &Path, modified: u128, size: u32) -> String {
+    let mut hasher = DefaultHasher::new();
+    (path, modified, size).hash(&mut hasher);
+
+    format!("{:016x}.jpg", hasher.finish())
+}
+
+/// EXIF 内嵌缩略图长边不小于 size 时直接使用, 不解码原图
+fn embedded_thumbnail(bytes: &[u8], size: u32) -> Option<Vec<u8>> {
+    let exif = Reader::new().read_from_container(&mut Cursor::new(bytes)).ok()?;
+    let data = thumbnail_data(&exif)?;
+    let (segments, _) = split_jpeg(data).ok()?;
+    let (width, height) = frame_size(&segments)?;
+
+    (width.max(height) >= size as usize).then(|| data.to_vec())
+}
+
+fn resize(bytes: &[u8], size: u32) -> anyhow::Result<Vec<u8>> {
+    let image = image::load_from_memory(bytes).map_err(|e|anyhow!(e))?.thumbnail(size, size).into_rgb8();
+
+    let mut encoded = Cursor::new(vec![]);
+    JpegEncoder::new_with_quality(&mut encoded, 80).encode_image(&image).map_err(|e|anyhow!(e))?;
+
+    Ok(encoded.into_inner())
+}
+
+#[test]
+fn test_parse_request() {
+    assert_eq!(parse_request("thumb://localhost/%2Fdata%2F%E5%B7%A1%E6%A3%80%2FDJI_0001.JPG"), Some(("/data/巡检/DJI_0001.JPG".to_string(), THUMBNAIL_SIZE)));
+    assert_eq!(parse_request("https://thumb.localhost/C%3A%5Cphotos%5CDJI_0001_T.JPG?size=320"), Some(("C:\\photos\\DJI_0001_T.JPG".to_string(), 320)));
+    assert_eq!(parse_request("thumb://localhost/a.jpg?size=100000").map(|v| v.1), Some(MAX_THUMBNAIL_SIZE));
+    assert_eq!(parse_request("thumb://localhost/%E5%B7"), None);
+    assert_eq!(parse_request("thumb://localhost/"), None);
+
+    let path = Path::new("/data/DJI_0001.JPG");
+    assert_eq!(cache_name(path, 1, 160), cache_name(path, 1, 160));
+    assert_ne!(cache_name(path, 1, 160), cache_name(path, 2, 160));
+    assert!(allowed_path("/etc/passwd").is_none());
+
+    let root = std::env::temp_dir().join("test_thumbnail_root");
+    let other = std::env::temp_dir().join("test_thumbnail_other");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::create_dir_all(&other).unwrap();
+    for file in [root.join("sub/DJI_0001.JPG"), root.join("sub/notes.txt"), other.join("DJI_0002.JPG")] {
+        fs::write(file, [0xFF, 0xD8, 0xFF, 0xD9]).unwrap();
+    }
+    THUMBNAIL_ROOTS.write().unwrap().photos = root.to_str().unwrap().to_string();
+
+    let photo = root.join("sub/DJI_0001.JPG");
+    assert_eq!(allowed_path(photo.to_str().unwrap()), Some(fs::canonicalize(&photo).unwrap()));
+    assert!(allowed_path(root.join("sub/../sub/DJI_0001.JPG").to_str().unwrap()).is_some());
+    assert!(allowed_path(root.join("sub/notes.txt").to_str().unwrap()).is_none());
+    assert!(allowed_path(root.join("../test_thumbnail_other/DJI_0002.JPG").to_str().unwrap()).is_none());
+
+    // 没有内嵌缩略图时需要后台生成
+    let (cache_file, cached) = cached_thumbnail(&photo, THUMBNAIL_SIZE, &root.join("cache")).unwrap();
+    assert!(cached.is_none());
+    assert!(cache_file.starts_with(root.join("cache")));
+
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_dir_all(&other).unwrap();
+}
@@ -0,0 +1,281 @@
+use std::fs;
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDateTime};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use tokio::sync::Mutex;
+use xml::EventReader;
+use xml::reader::XmlEvent;
+use crate::photo::{photo_list, Photo, PhotoError, PositionSource, PHOTOS_PATH};
+use crate::utils::to_invoke_err;
+
+/// 导入的飞行轨迹, 用于给没有 GPS 信息的照片定位
+pub static TRACK: Lazy<Mutex<Option<Track>>> = Lazy::new(|| { Mutex::new(None) });
+
+/// 照片前后两个轨迹点间隔超过该值(秒)时不插值
+pub static MAX_GAP: f64 = 10.0;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TrackPoint {
+    /// UTC 时间戳(秒)
+    pub timestamp: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub altitude: Option<f64>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Track {
+    /// 按时间排列的轨迹点
+    pub points: Vec<TrackPoint>,
+    /// 轨迹时间减 UTC 的秒数, 照片拍摄时间(UTC)加上该值即为轨迹时间, 如飞行记录为北京时间时取 28800
+    pub utc_offset: f64,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct TrackSummary {
+    pub points: usize,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// 按轨迹定位的照片数
+    pub geotagged: usize,
+    /// 仍然无法定位的照片
+    pub unlocated: Vec<String>,
+    /// 无法读取的照片
+    pub failed: Vec<PhotoError>,
+}
+
+impl Track {
+    pub fn new(mut points: Vec<TrackPoint>, utc_offset: f64) -> Self {
+        points.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Track { points, utc_offset }
+    }
+
+    /// 按拍摄时间在前后两个轨迹点之间线性插值, 超出轨迹范围或间隔过大时返回 None
+    pub fn locate(&self, timestamp: f64) -> Option<TrackPoint> {
+        let time = timestamp + self.utc_offset;
+        let idx = self.points.partition_point(|v| v.timestamp < time);
+
+        if let Some(point) = self.points.get(idx).filter(|v| v.timestamp == time) {
+            return Some(point.clone());
+        }
+        let (before, after) = (self.points.get(idx.checked_sub(1)?)?, self.points.get(idx)?);
+        if after.timestamp - before.timestamp > MAX_GAP {
+            return None;
+        }
+
+        let ratio = (time - before.timestamp) / (after.timestamp - before.timestamp);
+        let altitude = match (before.altitude, after.altitude) {
+            (Some(a), Some(b)) => Some(a + (b - a) * ratio),
+            _ => None,
+        };
+
+        Some(TrackPoint {
+            timestamp: time,
+            longitude: before.longitude + (after.longitude - before.longitude) * ratio,
+            latitude: before.latitude + (after.latitude - before.latitude) * ratio,
+            altitude,
+        })
+    }
+
+    /// 给没有 GPS 信息的照片定位, 定位成功返回 true
+    pub fn geotag(&self, photo: &mut Photo) -> bool {
+        let point = match photo.capture_timestamp().and_then(|v| self.locate(v as f64)) {
+            Some(point) => point,
+            None => return false,
+        };
+
+        photo.longitude = point.longitude;
+        photo.latitude = point.latitude;
+        photo.altitude = photo.altitude.or(point.altitude);
+        photo.position_source = PositionSource::Track;
+
+        true
+    }
+}
+
+/// 导入 GPX 轨迹或大疆飞行记录 CSV, 重新扫描照片目录并给没有 GPS 信息的照片定位
+///
+/// track_utc_offset 为轨迹时间的时区偏移(秒), 如飞行记录为北京时间时取 28800, 相机时钟偏差在 set_clock_offset 中设置
+#[tauri::command]
+pub async fn import_track(track_file: &str, track_utc_offset: Option<f64>) -> Result<String, InvokeError> {
+    let points = read_track(track_file).map_err(to_invoke_err)?;
+    let track = Track::new(points, track_utc_offset.unwrap_or_default());
+
+    let format = |v: Option<&TrackPoint>| v.and_then(|v| DateTime::from_timestamp(v.timestamp as i64, 0)).map(|v| v.naive_utc().to_string());
+    let mut summary = TrackSummary {
+        points: track.points.len(),
+        start: format(track.points.first()),
+        end: format(track.points.last()),
+        ..Default::default()
+    };
+    *TRACK.lock().await = Some(track);
+
+    let path = PHOTOS_PATH.lock().await.clone();
+    if !path.is_empty() {
+        let scan = photo_list(path.as_str()).await.map_err(to_invoke_err)?;
+        summary.geotagged = scan.photos.keys().filter(|v| v.position_source == PositionSource::Track).count();
+        summary.unlocated = scan.unlocated.iter().map(|v| v.file_name.clone()).collect();
+        summary.failed = scan.failed;
+    }
+
+    let json = serde_json::to_string(&summary).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 按扩展名读取 GPX 或 CSV 轨迹
+pub fn read_track(track_file: &str) -> anyhow::Result<Vec<TrackPoint>> {
+    let text = fs::read_to_string(track_file)?;
+
+    let points = if track_file.to_lowercase().ends_with(".gpx") {
+        parse_gpx(&text)?
+    } else if track_file.to_lowercase().ends_with(".csv") {
+        parse_flight_csv(&text)?
+    } else {
+        return Err(anyhow::Error::msg("not gpx or csv file"));
+    };
+
+    if points.is_empty() {
+        return Err(anyhow::Error::msg("no track point with time found"));
+    }
+
+    Ok(points)
+}
+
+/// 解析 GPX 中的 trkpt/rtept/wpt, 没有时间的点忽略
+pub fn parse_gpx(text: &str) -> anyhow::Result<Vec<TrackPoint>> {
+    let mut parser = EventReader::new(text.as_bytes());
+    let mut points = vec![];
+    let mut point: Option<TrackPoint> = None;
+    let mut element = String::new();
+
+    loop {
+        match parser.next().map_err(|e|anyhow!(e))? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                match name.local_name.as_str() {
+                    "trkpt" | "rtept" | "wpt" => {
+                        let attribute = |key: &str| attributes.iter().find(|v| v.name.local_name == key).and_then(|v| v.value.trim().parse().ok());
+                        point = match (attribute("lon"), attribute("lat")) {
+                            (Some(longitude), Some(latitude)) => Some(TrackPoint { timestamp: f64::NAN, longitude, latitude, altitude: None }),
+                            _ => None,
+                        };
+                    }
+                    other => element = other.to_string(),
+                }
+            }
+            XmlEvent::Characters(content) => {
+                if let Some(point) = point.as_mut() {
+                    match element.as_str() {
+                        "ele" => point.altitude = content.trim().parse().ok(),
+                        "time" => point.timestamp = parse_time(content.trim()).unwrap_or(f64::NAN),
+                        _ => {}
+                    }
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                match name.local_name.as_str() {
+                    "trkpt" | "rtept" | "wpt" => {
+                        points.extend(point.take().filter(|v| !v.timestamp.is_nan()));
+                    }
+                    _ => element.clear(),
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => {}
+        }
+    }
+
+    Ok(points)
+}
+
+/// 解析大疆飞行记录导出的 CSV, 按表头识别时间、经纬度和高度列
+///
+/// 时间取 "datetime" 列, 没有时取日期列和时间列拼接, 如 "CUSTOM.date [local]" 和 "CUSTOM.updateTime [local]"
+pub fn parse_flight_csv(text: &str) -> anyhow::Result<Vec<TrackPoint>> {
+    // 字段可能带引号并含有逗号, 各行列数也不一定相同
+    let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(text.as_bytes());
+    let header: Vec<String> = reader.headers().map_err(|e|anyhow!(e))?.iter().map(|v| v.to_lowercase()).collect();
+
+    let column = |keys: &[&str]| header.iter().position(|v| keys.iter().any(|k| v.contains(k)));
+    let longitude = column(&["longitude"]).ok_or(anyhow::Error::msg("longitude column not found"))?;
+    let latitude = column(&["latitude"]).ok_or(anyhow::Error::msg("latitude column not found"))?;
+    let altitude = column(&["altitude"]);
+    // 大疆的 "updateTime" 列只有时间
+    let datetime = header.iter().position(|v| v.contains("datetime") && !v.contains("updatetime"));
+    let date = header.iter().position(|v| v.contains("date") && !v.contains("time"));
+    let time = header.iter().position(|v| v.contains("time") && !v.contains("millisecond"));
+
+    let mut points = vec![];
+    for fields in reader.records() {
+        let fields = fields.map_err(|e|anyhow!(e))?;
+        let field = |idx: usize| fields.get(idx).unwrap_or_default();
+
+        let time_text = match (datetime, date, time) {
+            (Some(idx), _, _) => field(idx).to_string(),
+            (None, Some(date), Some(time)) => format!("{} {}", field(date), field(time)),
+            _ => return Err(anyhow::Error::msg("time column not found")),
+        };
+        let point = match (parse_time(time_text.as_str()), field(longitude).parse::<f64>(), field(latitude).parse::<f64>()) {
+            (Some(timestamp), Ok(longitude), Ok(latitude)) if longitude != 0.0 && latitude != 0.0 => TrackPoint {
+                timestamp,
+                longitude,
+                latitude,
+                altitude: altitude.and_then(|v| field(v).parse().ok()),
+            },
+            _ => continue,
+        };
+        points.push(point);
+    }
+
+    Ok(points)
+}
+
+/// 解析轨迹时间, 带时区的按时区换算, 不带时区的按 UTC, 返回秒级时间戳
+pub fn parse_time(text: &str) -> Option<f64> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.timestamp_millis() as f64 / 1000.0);
+    }
+
+    let formats = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y/%m/%d %H:%M:%S%.f", "%m/%d/%Y %I:%M:%S%.f %p", "%m/%d/%Y %H:%M:%S%.f"];
+    formats.iter()
+        .find_map(|v| NaiveDateTime::parse_from_str(text, v).ok())
+        .map(|v| v.and_utc().timestamp_millis() as f64 / 1000.0)
+}
+
+#[test]
+fn test_track() {
+    let gpx = r#"<?xml version="1.0"?><gpx><trk><trkseg>
+        <trkpt lat="30.0" lon="120.0"><ele>100</ele><time>2024-01-01T02:00:00Z</time></trkpt>
+        <trkpt lat="30.0" lon="120.001"><ele>110</ele><time>2024-01-01T02:00:04Z</time></trkpt>
+        <trkpt lat="30.001" lon="120.001"><time>2024-01-01T02:01:00Z</time></trkpt>
+        <trkpt lat="30.002" lon="120.001"></trkpt>
+    </trkseg></trk></gpx>"#;
+    let points = parse_gpx(gpx).unwrap();
+    assert_eq!(points.len(), 3);
+
+    let track = Track::new(points.clone(), 0.0);
+    let mut photo = Photo { capture_time: Some("2024-01-01 02:00:01".to_string()), ..Default::default() };
+    assert!(track.geotag(&mut photo));
+    assert!((photo.longitude - 120.00025).abs() < 1e-9);
+    assert_eq!(photo.altitude, Some(102.5));
+    assert_eq!(photo.position_source, PositionSource::Track);
+
+    // 轨迹点间隔过大、超出轨迹范围时不定位
+    assert!(track.locate(parse_time("2024-01-01 02:00:30").unwrap()).is_none());
+    assert!(track.locate(parse_time("2024-01-01 01:59:59").unwrap()).is_none());
+
+    // 轨迹为北京时间
+    let track = Track::new(points.iter().map(|v| TrackPoint { timestamp: v.timestamp + 28800.0, ..v.clone() }).collect(), 28800.0);
+    assert_eq!(track.locate(parse_time("2024-01-01 02:00:04").unwrap()).map(|v| v.longitude), Some(120.001));
+
+    let csv = "CUSTOM.date [local],CUSTOM.updateTime [local],OSD.latitude,OSD.longitude,OSD.altitude [m],APP.tip\n\
+        1/1/2024,10:00:00.00 AM,30.0,120.0,100\n\
+        1/1/2024,10:00:00.50 AM,0,0,0\n\
+        \"1/1/2024\",10:00:01.00 AM,30.0,120.0001,101,\"Low battery, returning home\"\n";
+    let points = parse_flight_csv(csv).unwrap();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[1].timestamp - points[0].timestamp, 1.0);
+    assert_eq!(points[1].altitude, Some(101.0));
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+use anyhow::anyhow;
+use tauri::InvokeError;
+use xlsxwriter::{Format, FormatColor, FormatUnderline, Workbook, Worksheet};
+use crate::handle::{station_folder, OUTPUT_PATH, OUTPUT_TEMPLATE};
+use crate::report::{current_report, InspectionReport};
+use crate::station::AttributeValue;
+use crate::station::line::multi_line;
+use crate::utils::{ensure_dir_exists, to_invoke_err};
+
+pub static REPORT_FILE_NAME: &str = "巡检报告.xlsx";
+
+/// 导出归属结果的Excel报告, 返回报告文件路径
+#[tauri::command]
+pub async fn export_excel_report(output_dir: &str) -> Result<String, InvokeError> {
+    ensure_dir_exists(output_dir).map_err(to_invoke_err)?;
+
+    let report = current_report().await.map_err(to_invoke_err)?;
+    let photo_output = OUTPUT_PATH.lock().await.clone();
+    let grouped = multi_line().await;
+    let template = OUTPUT_TEMPLATE.lock().await.clone();
+
+    let output_file = Path::new(output_dir).join(REPORT_FILE_NAME);
+    let output_file = output_file.to_str().ok_or(anyhow::Error::msg("report path is null")).map_err(to_invoke_err)?;
+
+    write_excel_report(&report, output_file, photo_output.as_str(), template.as_deref(), grouped).map_err(to_invoke_err)?;
+
+    Ok(output_file.to_string())
+}
+
+pub fn write_excel_report(report: &InspectionReport, output_file: &str, photo_output: &str, template: Option<&str>, grouped: bool) -> anyhow::Result<()> {
+    let workbook = Workbook::new(output_file).map_err(|e|anyhow!(e))?;
+
+    let mut header = Format::new();
+    header.set_bold();
+    let mut link = Format::new();
+    link.set_font_color(FormatColor::Blue).set_underline(FormatUnderline::Single);
+
+    let mut sheet = workbook.add_worksheet(Some("汇总")).map_err(|e|anyhow!(e))?;
+    write_summary(&mut sheet, report, &header)?;
+
+    let mut sheet = workbook.add_worksheet(Some("杆塔")).map_err(|e|anyhow!(e))?;
+    write_towers(&mut sheet, report, photo_output, template, grouped, &header, &link)?;
+
+    let mut sheet = workbook.add_worksheet(Some("未归属照片")).map_err(|e|anyhow!(e))?;
+    write_unassigned(&mut sheet, report, &header)?;
+
+    workbook.close().map_err(|e|anyhow!(e))?;
+
+    Ok(())
+}
+
+pub fn write_header(sheet: &mut Worksheet, titles: &[&str], format: &Format) -> anyhow::Result<()> {
+    for (col, title) in titles.iter().enumerate() {
+        sheet.write_string(0, col as u16, title, Some(format)).map_err(|e|anyhow!(e))?;
+    }
+    sheet.set_column(0, titles.len() as u16 - 1, 16.0, None).map_err(|e|anyhow!(e))?;
+    sheet.freeze_panes(1, 0);
+
+    Ok(())
+}
+
+fn write_summary(sheet: &mut Worksheet, report: &InspectionReport, header: &Format) -> anyhow::Result<()> {
+    write_header(sheet, &["项目", "数量"], header)?;
+
+    let rows = [
+        ("杆塔总数", report.towers.len() as f64),
+        ("有照片杆塔", report.covered_towers() as f64),
+        ("覆盖率(%)", (report.coverage() * 100.0).round() / 100.0),
+        ("普通照片", report.total.normal as f64),
+        ("红外照片", report.total.infrared as f64),
+        ("照片总数", (report.total.normal + report.total.infrared) as f64),
+        ("未归属照片", report.unassigned.len() as f64),
+    ];
+
+    for (idx, (name, value)) in rows.iter().enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_string(row, 0, name, None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 1, *value, None).map_err(|e|anyhow!(e))?;
+    }
+
+    Ok(())
+}
+
+fn write_towers(sheet: &mut Worksheet, report: &InspectionReport, photo_output: &str, template: Option<&str>, grouped: bool, header: &Format, link: &Format) -> anyhow::Result<()> {
+    // 台账中的其他属性依次列在最后
+    let mut keys: Vec<&String> = report.towers.iter().flat_map(|v| v.station.attributes.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut titles = vec!["杆塔编号", "普通", "红外", "首张拍摄时间", "末张拍摄时间", "最近照片距离(米)", "半径(米)", "状态", "输出目录", "塔型"];
+    titles.extend(keys.iter().map(|v| v.as_str()));
+    write_header(sheet, &titles, header)?;
+
+    for (idx, tower) in report.towers.iter().enumerate() {
+        let row = idx as u32 + 1;
+        let name = if grouped { tower.station.key() } else { tower.station.name.clone() };
+        sheet.write_string(row, 0, name.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 1, tower.count.normal as f64, None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 2, tower.count.infrared as f64, None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 3, tower.first_capture.as_deref().unwrap_or_default(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 4, tower.last_capture.as_deref().unwrap_or_default(), None).map_err(|e|anyhow!(e))?;
+        if let Some(distance) = tower.nearest_distance {
+            sheet.write_number(row, 5, (distance * 100.0).round() / 100.0, None).map_err(|e|anyhow!(e))?;
+        }
+        if let Some(radius) = tower.radius {
+            sheet.write_number(row, 6, (radius * 100.0).round() / 100.0, None).map_err(|e|anyhow!(e))?;
+        }
+        sheet.write_string(row, 7, tower.status.label(), None).map_err(|e|anyhow!(e))?;
+
+        if !photo_output.is_empty() && !tower.photos.is_empty() {
+            let dir = Path::new(photo_output).join(station_folder(&tower.station, template, grouped));
+            let url = format!("external:{}", dir.to_string_lossy());
+            sheet.write_url(row, 8, url.as_str(), Some(link)).map_err(|e|anyhow!(e))?;
+        }
+        sheet.write_string(row, 9, tower.station.tower_type.as_deref().unwrap_or_default(), None).map_err(|e|anyhow!(e))?;
+        for (col, key) in keys.iter().enumerate() {
+            match tower.station.attributes.get(*key) {
+                Some(AttributeValue::Number(v)) => sheet.write_number(row, col as u16 + 10, *v, None).map_err(|e|anyhow!(e))?,
+                Some(AttributeValue::Text(v)) => sheet.write_string(row, col as u16 + 10, v.as_str(), None).map_err(|e|anyhow!(e))?,
+                None => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_unassigned(sheet: &mut Worksheet, report: &InspectionReport, header: &Format) -> anyhow::Result<()> {
+    write_header(sheet, &["文件名", "类型", "经度", "纬度", "拍摄时间", "最近杆塔", "距离(米)", "路径"], header)?;
+
+    for (idx, v) in report.unassigned.iter().enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_string(row, 0, v.photo.file_name.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 1, v.photo.photo_type.label(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 2, v.photo.longitude, None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 3, v.photo.latitude, None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 4, v.photo.local_time.as_deref().unwrap_or_default(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 5, v.nearest_station.as_deref().unwrap_or_default(), None).map_err(|e|anyhow!(e))?;
+        if let Some(distance) = v.distance {
+            sheet.write_number(row, 6, (distance * 100.0).round() / 100.0, None).map_err(|e|anyhow!(e))?;
+        }
+        sheet.write_string(row, 7, v.photo.path.as_str(), None).map_err(|e|anyhow!(e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::fmt::Write;
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use tauri::InvokeError;
+use crate::handle::{distance_meters, ONE_METERS_TO_LATITUDE, ONE_METERS_TO_LONGITUDE};
+use crate::photo::{exif_thumbnail, PhotoType};
+use crate::report::{current_report, InspectionReport, TowerReport, TowerStatus};
+use crate::utils::{ensure_dir_exists, to_invoke_err};
+
+pub static REPORT_FILE_NAME: &str = "巡检报告.html";
+
+/// 示意图宽高(像素)
+static MAP_WIDTH: f64 = 960.0;
+static MAP_HEIGHT: f64 = 540.0;
+static MAP_PADDING: f64 = 30.0;
+
+static STYLE: &str = r#"
+body { font-family: sans-serif; margin: 24px; color: #222; }
+table { border-collapse: collapse; margin-bottom: 16px; }
+td, th { border: 1px solid #ccc; padding: 4px 10px; text-align: left; }
+section { border-top: 2px solid #ddd; padding-top: 8px; margin-top: 24px; }
+.grid { display: flex; flex-wrap: wrap; gap: 8px; }
+.thumb { width: 168px; font-size: 12px; }
+.thumb img { width: 160px; height: 120px; object-fit: cover; display: block; background: #eee; }
+.thumb .none { width: 160px; height: 120px; background: #eee; display: flex; align-items: center; justify-content: center; color: #999; }
+.Complete { color: #2a7d2a; } .MissingInfrared, .MissingNormal { color: #c27c0e; } .Empty { color: #c0392b; }
+"#;
+
+/// 导出离线可查看的HTML报告, 返回报告文件路径
+#[tauri::command]
+pub async fn export_html_report(output_dir: &str) -> Result<String, InvokeError> {
+    ensure_dir_exists(output_dir).map_err(to_invoke_err)?;
+
+    let report = current_report().await.map_err(to_invoke_err)?;
+
+    let output_file = Path::new(output_dir).join(REPORT_FILE_NAME);
+    let html = render_html(&report);
+    tokio::fs::write(&output_file, html).await.map_err(|e| to_invoke_err(e.into()))?;
+
+    Ok(output_file.to_string_lossy().to_string())
+}
+
+pub fn render_html(report: &InspectionReport) -> String {
+    let mut html = String::new();
+
+    html.push_str("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>巡检报告</title>");
+    let _ = write!(html, "<style>{}</style></head><body><h1>巡检报告</h1>", STYLE);
+
+    let _ = write!(
+        html,
+        "<table><tr><th>杆塔总数</th><td>{}</td></tr><tr><th>有照片杆塔</th><td>{}</td></tr>\
+         <tr><th>覆盖率</th><td>{:.2}%</td></tr><tr><th>普通照片</th><td>{}</td></tr>\
+         <tr><th>红外照片</th><td>{}</td></tr><tr><th>未归属照片</th><td>{}</td></tr></table>",
+        report.towers.len(),
+        report.covered_towers(),
+        report.coverage(),
+        report.total.normal,
+        report.total.infrared,
+        report.unassigned.len(),
+    );
+
+    html.push_str(&render_map(report));
+
+    for tower in report.towers.iter() {
+        html.push_str(&render_tower(tower));
+    }
+
+    if !report.unassigned.is_empty() {
+        html.push_str("<section><h2>未归属照片</h2><table><tr><th>文件名</th><th>类型</th><th>拍摄时间</th><th>最近杆塔</th><th>距离(米)</th></tr>");
+        for v in report.unassigned.iter() {
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&v.photo.file_name),
+                v.photo.photo_type.label(),
+                escape(v.photo.local_time.as_deref().unwrap_or_default()),
+                escape(v.nearest_station.as_deref().unwrap_or_default()),
+                v.distance.map(|d| format!("{:.1}", d)).unwrap_or_default(),
+            );
+        }
+        html.push_str("</table></section>");
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+fn render_tower(tower: &TowerReport) -> String {
+    let mut html = String::new();
+
+    let _ = write!(
+        html,
+        "<section><h2>{} <small class=\"{:?}\">{}</small></h2>\
+         <p>普通: {} 红外: {} 首张: {} 末张: {} 最近照片距离: {} 半径: {}</p>",
+        escape(&tower.station.name),
+        tower.status,
+        tower.status.label(),
+        tower.count.normal,
+        tower.count.infrared,
+        escape(tower.first_capture.as_deref().unwrap_or("-")),
+        escape(tower.last_capture.as_deref().unwrap_or("-")),
+        tower.nearest_distance.map(|d| format!("{:.1}米", d)).unwrap_or("-".to_string()),
+        tower.radius.map(|d| format!("{:.1}米", d)).unwrap_or("-".to_string()),
+    );
+
+    let attributes: Vec<String> = tower.station.tower_type.iter().map(|v| format!("塔型: {}", v))
+        .chain(tower.station.attributes.iter().map(|(k, v)| format!("{}: {}", k, v)))
+        .collect();
+    if !attributes.is_empty() {
+        let _ = write!(html, "<p>{}</p>", escape(&attributes.join(" ")));
+    }
+
+    for photo_type in [PhotoType::Normal, PhotoType::Infrared] {
+        let photos: Vec<_> = tower.photos.iter().filter(|v| v.photo_type == photo_type).collect();
+        if photos.is_empty() {
+            continue;
+        }
+
+        let _ = write!(html, "<h3>{}</h3><div class=\"grid\">", photo_type.label());
+        for photo in photos {
+            let img = match exif_thumbnail(photo.path.as_str()) {
+                Ok(Some(data)) => format!("<img src=\"data:image/jpeg;base64,{}\">", STANDARD.encode(data)),
+                _ => "<div class=\"none\">无缩略图</div>".to_string(),
+            };
+            let _ = write!(
+                html,
+                "<div class=\"thumb\">{}<div>{}</div><div>{:.1}米 {}</div></div>",
+                img,
+                escape(&photo.file_name),
+                distance_meters(&tower.station, photo),
+                escape(photo.local_time.as_deref().unwrap_or_default()),
+            );
+        }
+        html.push_str("</div>");
+    }
+
+    html.push_str("</section>");
+    html
+}
+
+/// 线路示意图: 杆塔按台账顺序连线, 照片按类型着色
+fn render_map(report: &InspectionReport) -> String {
+    let mut points: Vec<(f64, f64)> = report.towers.iter().map(|v| (v.station.longitude, v.station.latitude)).collect();
+    points.extend(report.towers.iter().flat_map(|v| v.photos.iter().map(|p| (p.longitude, p.latitude))));
+    points.extend(report.unassigned.iter().map(|v| (v.photo.longitude, v.photo.latitude)));
+
+    if points.is_empty() {
+        return String::new();
+    }
+
+    let to_meters = |(longitude, latitude): (f64, f64)| (longitude / ONE_METERS_TO_LONGITUDE, latitude / ONE_METERS_TO_LATITUDE);
+    let meters: Vec<(f64, f64)> = points.into_iter().map(to_meters).collect();
+    let min_x = meters.iter().map(|v| v.0).fold(f64::MAX, f64::min);
+    let max_x = meters.iter().map(|v| v.0).fold(f64::MIN, f64::max);
+    let min_y = meters.iter().map(|v| v.1).fold(f64::MAX, f64::min);
+    let max_y = meters.iter().map(|v| v.1).fold(f64::MIN, f64::max);
+
+    let scale = f64::min(
+        (MAP_WIDTH - MAP_PADDING * 2.0) / (max_x - min_x).max(1.0),
+        (MAP_HEIGHT - MAP_PADDING * 2.0) / (max_y - min_y).max(1.0),
+    );
+    let project = |longitude: f64, latitude: f64| {
+        let (x, y) = to_meters((longitude, latitude));
+        (MAP_PADDING + (x - min_x) * scale, MAP_HEIGHT - MAP_PADDING - (y - min_y) * scale)
+    };
+
+    let mut svg = String::new();
+    let _ = write!(svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\" style=\"border:1px solid #ccc\">", MAP_WIDTH, MAP_HEIGHT);
+
+    let line: Vec<String> = report.towers.iter()
+        .map(|v| project(v.station.longitude, v.station.latitude))
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect();
+    let _ = write!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"#888\" stroke-width=\"1.5\"/>", line.join(" "));
+
+    for photo in report.unassigned.iter().map(|v| &v.photo) {
+        let (x, y) = project(photo.longitude, photo.latitude);
+        let _ = write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"#aaa\"/>", x, y);
+    }
+    for photo in report.towers.iter().flat_map(|v| v.photos.iter()) {
+        let (x, y) = project(photo.longitude, photo.latitude);
+        let color = match photo.photo_type {
+            PhotoType::Normal => "#2e86de",
+            PhotoType::Infrared => "#e74c3c",
+        };
+        let _ = write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"{}\"/>", x, y, color);
+    }
+    for tower in report.towers.iter() {
+        let (x, y) = project(tower.station.longitude, tower.station.latitude);
+        let color = if tower.status == TowerStatus::Empty { "#c0392b" } else { "#222" };
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"6\" height=\"6\" fill=\"{}\"/><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\">{}</text>",
+            x - 3.0, y - 3.0, color, x + 5.0, y - 5.0, escape(&tower.station.name),
+        );
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_render_html() {
+    use crate::photo::Photo;
+    use crate::station::Station;
+
+    let tower = TowerReport {
+        station: Station { name: "<1>".to_string(), longitude: 120.0, latitude: 30.0, ..Default::default() },
+        photos: vec![Photo { longitude: 120.0001, latitude: 30.0, path: "not_exist_V.JPG".to_string(), file_name: "a_V.JPG".to_string(), ..Default::default() }],
+        ..Default::default()
+    };
+    let report = InspectionReport { towers: vec![tower], ..Default::default() };
+
+    let html = render_html(&report);
+
+    assert!(html.contains("&lt;1&gt;"));
+    assert!(html.contains("<svg"));
+    assert!(html.contains("无缩略图"));
+    assert!(!html.contains("src=\"http"));
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::handle::{Belong, BELONG_MAP, CalcPhotoResult, distance_meters, report_stations, STATION_RADIUS};
+use crate::photo::{PHOTOS, Photo, PhotoType};
+use crate::station::Station;
+
+pub mod excel;
+pub mod html;
+pub mod thermal;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum TowerStatus {
+    /// 普通和红外照片都有
+    Complete,
+    MissingInfrared,
+    MissingNormal,
+    #[default]
+    Empty,
+}
+
+impl TowerStatus {
+    pub fn from_result(result: &CalcPhotoResult) -> Self {
+        match (result.normal > 0, result.infrared > 0) {
+            (true, true) => TowerStatus::Complete,
+            (true, false) => TowerStatus::MissingInfrared,
+            (false, true) => TowerStatus::MissingNormal,
+            (false, false) => TowerStatus::Empty,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TowerStatus::Complete => "完整",
+            TowerStatus::MissingInfrared => "缺少红外",
+            TowerStatus::MissingNormal => "缺少普通",
+            TowerStatus::Empty => "无照片",
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct TowerReport {
+    pub station: Station,
+    pub count: CalcPhotoResult,
+    /// 首末照片的相机本地时间
+    pub first_capture: Option<String>,
+    pub last_capture: Option<String>,
+    /// 距杆塔最近的照片距离(米)
+    pub nearest_distance: Option<f64>,
+    /// 归属时使用的半径(米)
+    pub radius: Option<f64>,
+    pub status: TowerStatus,
+    pub photos: Vec<Photo>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct UnassignedPhoto {
+    pub photo: Photo,
+    pub nearest_station: Option<String>,
+    pub distance: Option<f64>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct InspectionReport {
+    pub total: CalcPhotoResult,
+    pub towers: Vec<TowerReport>,
+    pub unassigned: Vec<UnassignedPhoto>,
+}
+
+impl InspectionReport {
+    pub fn covered_towers(&self) -> usize {
+        self.towers.iter().filter(|v| v.status != TowerStatus::Empty).count()
+    }
+
+    /// 有照片的杆塔占比(百分比)
+    pub fn coverage(&self) -> f64 {
+        if self.towers.is_empty() {
+            return 0.0;
+        }
+        self.covered_towers() as f64 * 100.0 / self.towers.len() as f64
+    }
+}
+
+/// 根据最近一次归属使用的杆塔、当前照片和归属结果生成报告数据
+pub async fn current_report() -> anyhow::Result<InspectionReport> {
+    let stations = report_stations().await?;
+    let belong_map = BELONG_MAP.lock().await.clone();
+    let photos = PHOTOS.lock().await.clone();
+    let radii = STATION_RADIUS.lock().await.clone();
+
+    Ok(build_report(&stations, &belong_map, &photos, &radii))
+}
+
+pub fn build_report(
+    stations: &[Station],
+    belong_map: &HashMap<Station, HashMap<Photo, Belong>>,
+    photos: &HashMap<Photo, bool>,
+    radii: &HashMap<Station, f64>,
+) -> InspectionReport {
+    let mut report = InspectionReport::default();
+
+    for station in stations.iter() {
+        let mut tower = TowerReport {
+            station: station.clone(),
+            radius: radii.get(station).cloned(),
+            ..Default::default()
+        };
+
+        if let Some(photo_map) = belong_map.get(station) {
+            let mut photo_list: Vec<Photo> = photo_map.keys().cloned().collect();
+            photo_list.sort_by(|a, b| a.capture_time.cmp(&b.capture_time).then(a.file_name.cmp(&b.file_name)));
+
+            for photo in photo_list.iter() {
+                match photo.photo_type {
+                    PhotoType::Normal => tower.count.normal += 1,
+                    PhotoType::Infrared => tower.count.infrared += 1,
+                }
+            }
+
+            let times: Vec<String> = photo_list.iter().filter_map(|v| v.local_time.clone()).collect();
+            tower.first_capture = times.first().cloned();
+            tower.last_capture = times.last().cloned();
+            tower.nearest_distance = photo_list.iter().map(|v| distance_meters(station, v)).min_by(f64::total_cmp);
+            tower.photos = photo_list;
+        }
+
+        tower.status = TowerStatus::from_result(&tower.count);
+        report.total.normal += tower.count.normal;
+        report.total.infrared += tower.count.infrared;
+        report.towers.push(tower);
+    }
+
+    for photo in photos.keys() {
+        if belong_map.values().any(|v| v.contains_key(photo)) {
+            continue;
+        }
+
+        let nearest = stations.iter()
+            .map(|station| (station, distance_meters(station, photo)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        report.unassigned.push(UnassignedPhoto {
+            photo: photo.clone(),
+            nearest_station: nearest.map(|v| v.0.name.clone()),
+            distance: nearest.map(|v| v.1),
+        });
+    }
+    report.unassigned.sort_by(|a, b| a.photo.file_name.cmp(&b.photo.file_name));
+
+    report
+}
+
+#[test]
+fn test_build_report() {
+    use crate::fixtures::{station, timed_photo};
+
+    let photo = |name: &str, longitude: f64, photo_type: PhotoType, time: &str| Photo { photo_type, ..timed_photo(name, longitude, time) };
+
+    let stations = vec![station("1", 120.0), station("2", 120.01)];
+    let p1 = photo("a_V.JPG", 120.0001, PhotoType::Normal, "2024-01-01 10:00:05");
+    let p2 = photo("a_T.JPG", 120.0002, PhotoType::Infrared, "2024-01-01 10:00:01");
+    let p3 = photo("b_V.JPG", 120.005, PhotoType::Normal, "2024-01-01 10:05:00");
+
+    let mut belong_map = HashMap::new();
+    belong_map.insert(stations[0].clone(), HashMap::from([(p1.clone(), Belong::default()), (p2.clone(), Belong::default())]));
+    let photos = HashMap::from([(p1, true), (p2, true), (p3, true)]);
+
+    let report = build_report(&stations, &belong_map, &photos, &HashMap::new());
+
+    assert_eq!(report.total.normal, 1);
+    assert_eq!(report.total.infrared, 1);
+    assert_eq!(report.towers[0].status, TowerStatus::Complete);
+    assert_eq!(report.towers[0].first_capture.as_deref(), Some("2024-01-01 10:00:01"));
+    assert_eq!(report.towers[0].last_capture.as_deref(), Some("2024-01-01 10:00:05"));
+    assert!((report.towers[0].nearest_distance.unwrap() - 8.76).abs() < 0.1);
+    assert_eq!(report.towers[1].status, TowerStatus::Empty);
+    assert_eq!(report.unassigned.len(), 1);
+    assert_eq!(report.coverage(), 50.0);
+}
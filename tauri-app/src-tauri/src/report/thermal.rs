@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use xlsxwriter::{Format, Workbook, Worksheet};
+use crate::analysis::thermal::{analyse_loaded_photos, PhotoThermal, THERMAL, THERMAL_OPTIONS};
+use crate::handle::{report_stations, Belong, BELONG_MAP};
+use crate::photo::Photo;
+use crate::report::excel::write_header;
+use crate::station::Station;
+use crate::utils::{ensure_dir_exists, to_invoke_err};
+
+pub static THERMAL_REPORT_FILE_NAME: &str = "红外缺陷报告.xlsx";
+
+pub static THERMAL_JSON_FILE_NAME: &str = "红外缺陷报告.json";
+
+/// 缺陷等级判定阈值, 参照 DL/T 664 电流致热型设备的判断方法
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GradeOptions {
+    /// 相对温差(%)达到该值为一般缺陷
+    pub general_delta: f64,
+    /// 相对温差(%)达到该值为严重缺陷
+    pub serious_delta: f64,
+    /// 相对温差(%)达到该值为危急缺陷
+    pub critical_delta: f64,
+    /// 热点温度(℃)超过该值为严重缺陷
+    pub serious_temperature: f64,
+    /// 热点温度(℃)超过该值为危急缺陷
+    pub critical_temperature: f64,
+    /// 温升(K)小于该值时不按相对温差判断
+    pub min_rise: f64,
+    /// 各杆塔正常相同部位的参考温度 T2(℃), 以 Station::key() 为键, 没有参考温度的杆塔只按热点温度判断
+    pub references: HashMap<String, f64>,
+}
+
+impl Default for GradeOptions {
+    fn default() -> Self {
+        GradeOptions {
+            general_delta: 35.0,
+            serious_delta: 80.0,
+            critical_delta: 95.0,
+            serious_temperature: 80.0,
+            critical_temperature: 110.0,
+            min_rise: 15.0,
+            references: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThermalGrade {
+    #[default]
+    Normal,
+    General,
+    Serious,
+    Critical,
+}
+
+impl ThermalGrade {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThermalGrade::Normal => "正常",
+            ThermalGrade::General => "一般",
+            ThermalGrade::Serious => "严重",
+            ThermalGrade::Critical => "危急",
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct TowerDefect {
+    pub line: String,
+    pub station: String,
+    pub asset_id: String,
+    pub photo_count: usize,
+    /// 最高温的红外照片
+    pub hottest: PhotoThermal,
+    /// 热点温度 T1(℃)
+    pub hotspot: f64,
+    /// 参考温度 T2(℃), 由 GradeOptions 指定
+    pub reference: Option<f64>,
+    /// 环境温度 T0(℃)
+    pub ambient: f64,
+    /// 温升 T1 - T0(K)
+    pub rise: f64,
+    /// 温差 T1 - T2(K)
+    pub difference: Option<f64>,
+    /// 相对温差 (T1 - T2) / (T1 - T0)(%)
+    pub delta: Option<f64>,
+    pub grade: ThermalGrade,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct ThermalReport {
+    pub ambient: f64,
+    /// 有红外测温数据的杆塔, 按缺陷等级从高到低排列
+    pub towers: Vec<TowerDefect>,
+}
+
+impl ThermalReport {
+    pub fn count(&self, grade: ThermalGrade) -> usize {
+        self.towers.iter().filter(|v| v.grade == grade).count()
+    }
+}
+
+/// 导出红外缺陷报告的 Excel 和 JSON 文件, 返回两个文件的路径
+///
+/// 还没有测温结果时先按最近一次的测温参数解析红外照片
+#[tauri::command]
+pub async fn export_thermal_report(output_dir: &str, options: Option<GradeOptions>) -> Result<String, InvokeError> {
+    ensure_dir_exists(output_dir).map_err(to_invoke_err)?;
+
+    let options = options.unwrap_or_default();
+    let thermal_options = THERMAL_OPTIONS.lock().await.clone();
+    let ambient = thermal_options.ambient;
+
+    let mut thermal = THERMAL.lock().await.clone();
+    if thermal.is_empty() {
+        thermal = analyse_loaded_photos(thermal_options).await.map_err(to_invoke_err)?.0;
+    }
+
+    // 与巡检报告相同, 只统计最近一次归属使用的杆塔
+    let stations = report_stations().await.map_err(to_invoke_err)?;
+    let belong_map = BELONG_MAP.lock().await.clone();
+    let report = build_thermal_report(&stations, &belong_map, &thermal, ambient, &options);
+
+    let excel_file = Path::new(output_dir).join(THERMAL_REPORT_FILE_NAME);
+    let excel_file = excel_file.to_str().ok_or(anyhow::Error::msg("report path is null")).map_err(to_invoke_err)?;
+    write_thermal_excel(&report, excel_file).map_err(to_invoke_err)?;
+
+    let json_file = Path::new(output_dir).join(THERMAL_JSON_FILE_NAME);
+    let json = serde_json::to_string_pretty(&report).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+    fs::write(&json_file, json).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    let files = vec![excel_file.to_string(), json_file.to_string_lossy().to_string()];
+    let json = serde_json::to_string(&files).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 按热点温度和相对温差判定缺陷等级, 取两者中较高的等级, 没有参考温度时只按热点温度判断
+///
+/// 全图温度以背景为主, 不能作为参考温度
+pub fn grade(hotspot: f64, reference: Option<f64>, ambient: f64, options: &GradeOptions) -> (Option<f64>, ThermalGrade) {
+    let rise = hotspot - ambient;
+    let delta = reference.map(|v| if rise > 0.0 { (hotspot - v) / rise * 100.0 } else { 0.0 });
+    // 温升较小时相对温差没有意义
+    let by_delta = rise >= options.min_rise;
+    let delta_at_least = |threshold: f64| by_delta && delta.is_some_and(|v| v >= threshold);
+
+    let grade = if hotspot > options.critical_temperature || delta_at_least(options.critical_delta) {
+        ThermalGrade::Critical
+    } else if hotspot > options.serious_temperature || delta_at_least(options.serious_delta) {
+        ThermalGrade::Serious
+    } else if delta_at_least(options.general_delta) {
+        ThermalGrade::General
+    } else {
+        ThermalGrade::Normal
+    };
+
+    (delta, grade)
+}
+
+pub fn build_thermal_report(
+    stations: &[Station],
+    belong_map: &HashMap<Station, HashMap<Photo, Belong>>,
+    thermal: &HashMap<String, PhotoThermal>,
+    ambient: f64,
+    options: &GradeOptions,
+) -> ThermalReport {
+    let mut towers: Vec<TowerDefect> = stations.iter().filter_map(|station| {
+        let photos: Vec<&PhotoThermal> = belong_map.get(station)?.keys().filter_map(|v| thermal.get(&v.path)).collect();
+        let hottest = photos.iter().max_by(|a, b| a.stats.max.total_cmp(&b.stats.max))?;
+
+        let (hotspot, reference) = (hottest.stats.max, options.references.get(&station.key()).cloned());
+        let (delta, grade) = grade(hotspot, reference, ambient, options);
+
+        Some(TowerDefect {
+            line: station.line.clone(),
+            station: station.name.clone(),
+            asset_id: station.identity().to_string(),
+            photo_count: photos.len(),
+            hottest: (*hottest).clone(),
+            hotspot,
+            reference,
+            ambient,
+            rise: hotspot - ambient,
+            difference: reference.map(|v| hotspot - v),
+            delta,
+            grade,
+        })
+    }).collect();
+
+    // 台账顺序不变, 等级高的排在前面
+    towers.sort_by_key(|v| std::cmp::Reverse(v.grade));
+
+    ThermalReport { ambient, towers }
+}
+
+pub fn write_thermal_excel(report: &ThermalReport, output_file: &str) -> anyhow::Result<()> {
+    let workbook = Workbook::new(output_file).map_err(|e|anyhow!(e))?;
+
+    let mut header = Format::new();
+    header.set_bold();
+
+    let mut sheet = workbook.add_worksheet(Some("汇总")).map_err(|e|anyhow!(e))?;
+    write_header(&mut sheet, &["缺陷等级", "杆塔数"], &header)?;
+    let grades = [ThermalGrade::Critical, ThermalGrade::Serious, ThermalGrade::General, ThermalGrade::Normal];
+    for (idx, grade) in grades.iter().enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_string(row, 0, grade.label(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 1, report.count(*grade) as f64, None).map_err(|e|anyhow!(e))?;
+    }
+
+    let mut sheet = workbook.add_worksheet(Some("杆塔")).map_err(|e|anyhow!(e))?;
+    write_defects(&mut sheet, report, &header)?;
+
+    workbook.close().map_err(|e|anyhow!(e))?;
+
+    Ok(())
+}
+
+fn write_defects(sheet: &mut Worksheet, report: &ThermalReport, header: &Format) -> anyhow::Result<()> {
+    write_header(sheet, &["线路", "杆塔编号", "资产编号", "缺陷等级", "热点温度(℃)", "参考温度(℃)", "环境温度(℃)", "温升(K)", "温差(K)", "相对温差(%)", "最高温照片", "热点位置", "红外照片数"], header)?;
+
+    let round = |v: f64| (v * 10.0).round() / 10.0;
+    for (idx, tower) in report.towers.iter().enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_string(row, 0, tower.line.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 1, tower.station.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 2, tower.asset_id.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 3, tower.grade.label(), None).map_err(|e|anyhow!(e))?;
+        // 没有参考温度时参考温度、温差和相对温差留空
+        let values = [Some(tower.hotspot), tower.reference, Some(tower.ambient), Some(tower.rise), tower.difference, tower.delta];
+        for (col, value) in values.iter().enumerate() {
+            if let Some(value) = value {
+                sheet.write_number(row, col as u16 + 4, round(*value), None).map_err(|e|anyhow!(e))?;
+            }
+        }
+        sheet.write_string(row, 10, tower.hottest.file_name.as_str(), None).map_err(|e|anyhow!(e))?;
+        let [x, y] = tower.hottest.stats.hotspot;
+        sheet.write_string(row, 11, format!("({}, {})", x, y).as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_number(row, 12, tower.photo_count as f64, None).map_err(|e|anyhow!(e))?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_build_thermal_report() {
+    use crate::photo::thermal::ThermalStats;
+
+    let options = GradeOptions::default();
+    assert_eq!(grade(30.0, Some(25.0), 25.0, &options).1, ThermalGrade::Normal);
+    assert_eq!(grade(50.0, Some(30.0), 20.0, &options).1, ThermalGrade::General);
+    assert_eq!(grade(85.0, Some(30.0), 20.0, &options).1, ThermalGrade::Serious);
+    assert_eq!(grade(70.0, Some(21.0), 20.0, &options).1, ThermalGrade::Critical);
+    assert_eq!(grade(120.0, Some(100.0), 20.0, &options).1, ThermalGrade::Critical);
+    // 没有参考温度时只按热点温度判断
+    assert_eq!(grade(70.0, None, 20.0, &options), (None, ThermalGrade::Normal));
+    assert_eq!(grade(85.0, None, 20.0, &options).1, ThermalGrade::Serious);
+
+    use crate::fixtures::{photo, station};
+
+    let thermal = |path: &str, max: f64, median: f64| (path.to_string(), PhotoThermal {
+        path: path.to_string(),
+        file_name: path.to_string(),
+        stats: ThermalStats { max, median, ..Default::default() },
+        ..Default::default()
+    });
+
+    let stations = vec![station("1", 120.0), station("2", 120.004), station("3", 120.008)];
+    let belong_map = HashMap::from([
+        (stations[0].clone(), HashMap::from([(photo("a", 120.0001), Belong::default()), (photo("bb", 120.0002), Belong::default())])),
+        (stations[1].clone(), HashMap::from([(photo("ccc", 120.0041), Belong::default())])),
+    ]);
+    let thermal = HashMap::from([thermal("a", 30.0, 25.0), thermal("bb", 90.0, 30.0), thermal("ccc", 28.0, 26.0)]);
+
+    let options = GradeOptions { references: HashMap::from([("2".to_string(), 20.0)]), ..Default::default() };
+    let report = build_thermal_report(&stations, &belong_map, &thermal, 20.0, &options);
+    assert_eq!(report.towers.len(), 2);
+    assert_eq!(report.towers[0].station, "1");
+    assert_eq!(report.towers[0].hottest.path, "bb");
+    assert_eq!(report.towers[0].grade, ThermalGrade::Serious);
+    assert_eq!(report.towers[0].photo_count, 2);
+    assert_eq!(report.towers[0].reference, None);
+    assert_eq!(report.towers[1].reference, Some(20.0));
+    assert_eq!(report.count(ThermalGrade::Normal), 1);
+}
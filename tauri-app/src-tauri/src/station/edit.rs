@@ -0,0 +1,295 @@
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+use tauri::InvokeError;
+use crate::handle::reassign_stations;
+use crate::station::{Station, STATION};
+use crate::station::excel::write_station_excel;
+use crate::station::kml::write_station_kml;
+use crate::station::line::{line_tree, LINES};
+use crate::station::order::STATION_ORDER;
+use crate::station::validate::{validate_stations, warnings_node};
+use crate::utils::{file_name, is_excel_file, is_kml_file, new_invoke_err, to_invoke_err};
+
+/// 最多保留的撤销步数
+pub static MAX_HISTORY: usize = 50;
+
+/// 台账修改历史, 保存修改前后的完整台账
+pub static HISTORY: Lazy<Mutex<LedgerHistory>> = Lazy::new(|| {
+    Mutex::new(LedgerHistory::default())
+});
+
+#[derive(Default, Debug, Clone)]
+pub struct LedgerHistory {
+    pub undo: Vec<Vec<Station>>,
+    pub redo: Vec<Vec<Station>>,
+}
+
+impl LedgerHistory {
+    /// 记录修改前的台账, 新的修改会清空重做记录
+    pub fn record(&mut self, stations: Vec<Station>) {
+        self.undo.push(stations);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum LedgerEdit {
+    /// 新增杆塔, 不指定 index 时追加到末尾
+    Add { station: Station, index: Option<usize> },
+    /// 修改杆塔坐标
+    Move { name: String, longitude: f64, latitude: f64, height: Option<f64> },
+    Rename { name: String, new_name: String },
+    Delete { name: String },
+    /// 在 name 与下一基之间插入杆塔(如 "N12+1"), 不指定坐标时取档距中点
+    Split { name: String, new_name: String, longitude: Option<f64>, latitude: Option<f64> },
+    /// 按 order 中的杆塔编号重排线路内的杆塔, 需包含线路的全部杆塔
+    Reorder { order: Vec<String> },
+}
+
+/// 修改台账, 重新归属受影响的杆塔, 返回修改后的线路树
+///
+/// 多条线路时用 line 指定杆塔所属线路, 不指定时取第一条线路
+#[tauri::command]
+pub async fn edit_station(edit: LedgerEdit, line: Option<String>) -> Result<String, InvokeError> {
+    let mut stations = STATION.lock().await;
+
+    let old = stations.clone();
+    let line = line.or(stations.first().map(|v| v.line.clone())).unwrap_or_default();
+    apply_edit(&mut stations, &edit, line.as_str()).map_err(to_invoke_err)?;
+    HISTORY.lock().await.record(old.clone());
+
+    let new = stations.clone();
+    drop(stations);
+
+    ledger_changed(&old, &new).await
+}
+
+#[tauri::command]
+pub async fn undo_station_edit() -> Result<String, InvokeError> {
+    let mut stations = STATION.lock().await;
+    let mut history = HISTORY.lock().await;
+
+    let previous = history.undo.pop().ok_or(new_invoke_err("nothing to undo"))?;
+    let old = std::mem::replace(&mut *stations, previous);
+    history.redo.push(old.clone());
+
+    let new = stations.clone();
+    drop(history);
+    drop(stations);
+
+    ledger_changed(&old, &new).await
+}
+
+#[tauri::command]
+pub async fn redo_station_edit() -> Result<String, InvokeError> {
+    let mut stations = STATION.lock().await;
+    let mut history = HISTORY.lock().await;
+
+    let next = history.redo.pop().ok_or(new_invoke_err("nothing to redo"))?;
+    let old = std::mem::replace(&mut *stations, next);
+    history.undo.push(old.clone());
+
+    let new = stations.clone();
+    drop(history);
+    drop(stations);
+
+    ledger_changed(&old, &new).await
+}
+
+/// 将一条线路的台账写回 KML 或 Excel, 按文件扩展名区分, 不指定线路时为第一条线路
+///
+/// 重新导入时以文件名作为线路标识, 因此每个文件只写一条线路
+#[tauri::command]
+pub async fn save_ledger(output_file: &str, line: Option<String>) -> Result<(), InvokeError> {
+    let stations = STATION.lock().await.clone();
+    let line = line.or(stations.first().map(|v| v.line.clone())).unwrap_or_default();
+    let stations: Vec<Station> = stations.into_iter().filter(|v| v.line == line).collect();
+    if stations.is_empty() {
+        return Err(new_invoke_err("line not found"));
+    }
+
+    if is_kml_file(output_file) {
+        let line_name = file_name(output_file).map_err(to_invoke_err)?;
+        write_station_kml(&stations, line_name.as_str(), output_file).map_err(to_invoke_err)?;
+    } else if is_excel_file(output_file) {
+        write_station_excel(&stations, output_file).map_err(to_invoke_err)?;
+    } else {
+        return Err(new_invoke_err("not kml or excel file"));
+    }
+
+    Ok(())
+}
+
+async fn ledger_changed(old: &[Station], new: &[Station]) -> Result<String, InvokeError> {
+    reassign_stations(&affected_stations(old, new)).await.map_err(to_invoke_err)?;
+
+    // 与导入台账时返回的结构相同
+    let warnings = validate_stations(new);
+    let lines = LINES.lock().await.clone();
+    let mut tree_node_list = line_tree(new, &lines, *STATION_ORDER.lock().await);
+    tree_node_list.extend(warnings_node(&warnings));
+
+    let json = serde_json::to_string(&tree_node_list).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+fn find_index(stations: &[Station], line: &str, name: &str) -> anyhow::Result<usize> {
+    stations.iter().position(|v| v.line == line && v.name == name).ok_or(anyhow::Error::msg(format!("station {} not found", name)))
+}
+
+fn ensure_unique(stations: &[Station], line: &str, name: &str) -> anyhow::Result<()> {
+    if stations.iter().any(|v| v.line == line && (v.name == name || v.identity() == name)) {
+        return Err(anyhow::Error::msg(format!("station {} already exists", name)));
+    }
+
+    Ok(())
+}
+
+pub fn apply_edit(stations: &mut Vec<Station>, edit: &LedgerEdit, line: &str) -> anyhow::Result<()> {
+    match edit {
+        LedgerEdit::Add { station, index } => {
+            ensure_unique(stations, line, station.name.as_str())?;
+            // index 为线路内的序号
+            let positions: Vec<usize> = stations.iter().enumerate().filter(|(_, v)| v.line == line).map(|(idx, _)| idx).collect();
+            let index = match index.and_then(|v| positions.get(v)) {
+                Some(idx) => *idx,
+                None => positions.last().map_or(stations.len(), |v| v + 1),
+            };
+            let id = if station.id.is_empty() { station.name.clone() } else { station.id.clone() };
+            stations.insert(index, Station { line: line.to_string(), id, ..station.clone() });
+        }
+        LedgerEdit::Move { name, longitude, latitude, height } => {
+            let idx = find_index(stations, line, name)?;
+            let station = &mut stations[idx];
+            station.longitude = *longitude;
+            station.latitude = *latitude;
+            if let Some(height) = height {
+                station.height = *height;
+            }
+        }
+        LedgerEdit::Rename { name, new_name } => {
+            let idx = find_index(stations, line, name)?;
+            ensure_unique(stations, line, new_name)?;
+            // 资产编号不随改名变化
+            if stations[idx].id.is_empty() {
+                stations[idx].id = stations[idx].name.clone();
+            }
+            stations[idx].name = new_name.clone();
+        }
+        LedgerEdit::Delete { name } => {
+            let idx = find_index(stations, line, name)?;
+            stations.remove(idx);
+        }
+        LedgerEdit::Split { name, new_name, longitude, latitude } => {
+            let idx = find_index(stations, line, name)?;
+            ensure_unique(stations, line, new_name)?;
+            let from = &stations[idx];
+            let to = stations.get(idx + 1).filter(|v| v.line == line).unwrap_or(from);
+
+            let station = Station {
+                line: line.to_string(),
+                id: new_name.clone(),
+                name: new_name.clone(),
+                longitude: longitude.unwrap_or((from.longitude + to.longitude) / 2.0),
+                latitude: latitude.unwrap_or((from.latitude + to.latitude) / 2.0),
+                height: (from.height + to.height) / 2.0,
+                tower_type: from.tower_type.clone(),
+                ..Default::default()
+            };
+            stations.insert(idx + 1, station);
+        }
+        LedgerEdit::Reorder { order } => {
+            let positions: Vec<usize> = stations.iter().enumerate().filter(|(_, v)| v.line == line).map(|(idx, _)| idx).collect();
+            if order.len() != positions.len() {
+                return Err(anyhow::Error::msg("order does not match stations of line"));
+            }
+            // 编号重复时依次对应台账中的各基
+            let mut used = vec![false; positions.len()];
+            let mut ordered = vec![];
+            for name in order.iter() {
+                let idx = (0..positions.len()).find(|v| !used[*v] && stations[positions[*v]].name == *name)
+                    .ok_or(anyhow::Error::msg(format!("station {} not found", name)))?;
+                used[idx] = true;
+                ordered.push(stations[positions[idx]].clone());
+            }
+            for (idx, station) in positions.into_iter().zip(ordered) {
+                stations[idx] = station;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 修改前后不同的杆塔, 以及相邻杆塔有变化的杆塔(自适应半径依赖相邻档距), 返回 Station::key()
+pub fn affected_stations(old: &[Station], new: &[Station]) -> Vec<String> {
+    let neighbours = |stations: &[Station], idx: usize| {
+        (idx.checked_sub(1).and_then(|v| stations.get(v)).cloned(), stations.get(idx + 1).cloned())
+    };
+
+    let same = |a: &Option<Station>, b: &Option<Station>| match (a, b) {
+        (Some(a), Some(b)) => a.same_as(b),
+        (None, None) => true,
+        _ => false,
+    };
+
+    let mut affected: Vec<String> = old.iter().filter(|v| !new.iter().any(|n| n.same_as(v)))
+        .chain(new.iter().filter(|v| !old.iter().any(|o| o.same_as(v))))
+        .map(|v| v.key())
+        .collect();
+
+    for (idx, station) in new.iter().enumerate() {
+        if let Some(old_idx) = old.iter().position(|v| v.same_as(station)) {
+            let (old_prev, old_next) = neighbours(old, old_idx);
+            let (new_prev, new_next) = neighbours(new, idx);
+            if !same(&old_prev, &new_prev) || !same(&old_next, &new_next) {
+                affected.push(station.key());
+            }
+        }
+    }
+
+    affected.sort();
+    affected.dedup();
+
+    affected
+}
+
+#[test]
+fn test_apply_edit() {
+    use crate::fixtures::station;
+
+    let old = vec![station("1", 120.0), station("2", 120.004), station("3", 120.008), station("4", 120.012)];
+    let mut stations = old.clone();
+
+    apply_edit(&mut stations, &LedgerEdit::Split { name: "2".to_string(), new_name: "2+1".to_string(), longitude: None, latitude: None }, "").unwrap();
+    assert_eq!(stations[2].name, "2+1");
+    assert!((stations[2].longitude - 120.006).abs() < 1e-9);
+
+    apply_edit(&mut stations, &LedgerEdit::Move { name: "4".to_string(), longitude: 120.013, latitude: 30.0, height: None }, "").unwrap();
+    apply_edit(&mut stations, &LedgerEdit::Delete { name: "1".to_string() }, "").unwrap();
+    assert!(apply_edit(&mut stations, &LedgerEdit::Rename { name: "3".to_string(), new_name: "2".to_string() }, "").is_err());
+
+    let affected = affected_stations(&old, &stations);
+    assert_eq!(affected, vec!["1", "2", "2+1", "3", "4"]);
+
+    let mut reordered = old.clone();
+    let order = vec!["1".to_string(), "3".to_string(), "2".to_string(), "4".to_string()];
+    apply_edit(&mut reordered, &LedgerEdit::Reorder { order }, "").unwrap();
+    let names: Vec<&str> = reordered.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, vec!["1", "3", "2", "4"]);
+    assert!(apply_edit(&mut reordered, &LedgerEdit::Reorder { order: vec!["1".to_string()] }, "").is_err());
+
+    let mut history = LedgerHistory::default();
+    history.record(old.clone());
+    history.redo.push(vec![]);
+    history.record(stations.clone());
+    assert_eq!(history.undo.len(), 2);
+    assert!(history.redo.is_empty());
+}
@@ -2,57 +2,110 @@ use std::mem;
 use anyhow::anyhow;
 use calamine::{DataType, open_workbook_auto, RangeDeserializerBuilder, Reader, Xlsx};
 use tauri::InvokeError;
+use xlsxwriter::Workbook;
 use crate::utils::{is_excel_file, file_name, new_invoke_err, to_invoke_err};
-use crate::station::{Station, STATION, TreeNode};
+use crate::station::{AttributeValue, Station};
+use crate::station::line::load_line;
 
 #[tauri::command]
-pub async fn excel_to_json(excel_file: &str) -> Result<String, InvokeError> {
+pub async fn excel_to_json(excel_file: &str, append: Option<bool>) -> Result<String, InvokeError> {
 
     if !is_excel_file(excel_file) {
         return Err(new_invoke_err("not excel file"));
     }
 
+    let station_data = excel_to_station_list(excel_file).map_err(to_invoke_err)?;
+
+    let line_name = file_name(excel_file).map_err(to_invoke_err)?;
+    let json = load_line(line_name.as_str(), station_data, append.unwrap_or_default()).await.map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 读取 Excel 台账, 第一行为表头
+pub fn excel_to_station_list(excel_file: &str) -> anyhow::Result<Vec<Station>> {
     let mut station_data = vec![];
 
-    let mut workbook = open_workbook_auto(excel_file).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+    let mut workbook = open_workbook_auto(excel_file).map_err(|e|anyhow!(e))?;
 
     let sheet_names = workbook.sheet_names();
-    let first_sheet_name = sheet_names.first().ok_or(anyhow::Error::msg("sheet1 not exist")).map_err(to_invoke_err)?;
-    let range = workbook.worksheet_range(first_sheet_name.as_str()).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+    let first_sheet_name = sheet_names.first().ok_or(anyhow::Error::msg("sheet1 not exist"))?;
+    let range = workbook.worksheet_range(first_sheet_name.as_str()).map_err(|e|anyhow!(e))?;
+
+    // 前 4 列依次为杆塔编号、经纬度和高度, 之后的列按列名识别半径、塔型、资产编号, 其余作为属性
+    let mut headers = vec![];
 
     for (idx,x) in range.rows().enumerate() {
         if idx == 0 {
+            headers = x.iter().map(|v| v.to_string().trim().to_string()).collect();
             continue
         }
 
-        let name = &x[0].as_string().ok_or(new_invoke_err("name is null"))?;
-        let longitude = &x[1].as_string().ok_or(new_invoke_err("longitude is null"))?;
-        let latitude = &x[2].as_string().ok_or(new_invoke_err("latitude is null"))?;
-        let height = &x[3].as_string().ok_or(new_invoke_err("height is null"))?;
-
-        let station = Station {
+        let name = &x[0].as_string().ok_or(anyhow::Error::msg("name is null"))?;
+        let longitude = &x[1].as_string().ok_or(anyhow::Error::msg("longitude is null"))?;
+        let latitude = &x[2].as_string().ok_or(anyhow::Error::msg("latitude is null"))?;
+        let height = &x[3].as_string().ok_or(anyhow::Error::msg("height is null"))?;
+        let mut station = Station {
             name: name.clone(),
-            longitude: longitude.parse().map_err(|e: std::num::ParseFloatError| new_invoke_err(e.to_string().as_str()))?,
-            latitude: latitude.parse().map_err(|e: std::num::ParseFloatError| new_invoke_err(e.to_string().as_str()))?,
-            height: height.parse().map_err(|e: std::num::ParseFloatError| new_invoke_err(e.to_string().as_str()))?,
+            longitude: longitude.parse().map_err(|e: std::num::ParseFloatError| anyhow!(e))?,
+            latitude: latitude.parse().map_err(|e: std::num::ParseFloatError| anyhow!(e))?,
+            height: height.parse().map_err(|e: std::num::ParseFloatError| anyhow!(e))?,
+            ..Default::default()
         };
+        for (col, header) in headers.iter().enumerate().skip(4) {
+            if let Some(value) = x.get(col).filter(|_| !header.is_empty()) {
+                station.set_attribute(header, value.to_string().as_str());
+            }
+        }
 
         station_data.push(station);
 
     }
 
-    *STATION.lock().await = station_data.clone();
+    Ok(station_data)
+}
 
-    let station_node: Vec<TreeNode> = station_data.into_iter().map(|v|v.into()).collect();
+/// 将台账写为 Excel, 列与 excel_to_json 读取的一致
+pub fn write_station_excel(stations: &[Station], output_file: &str) -> anyhow::Result<()> {
+    let workbook = Workbook::new(output_file).map_err(|e|anyhow!(e))?;
+    let mut sheet = workbook.add_worksheet(None).map_err(|e|anyhow!(e))?;
 
-    let line_name = file_name(excel_file).map_err(to_invoke_err)?;
-    let line_node = TreeNode{
-        key: line_name.clone(),
-        label: line_name.clone(),
-        children: Some(station_node),
-    };
+    let mut keys: Vec<&String> = stations.iter().flat_map(|v| v.attributes.keys()).collect();
+    keys.sort();
+    keys.dedup();
 
-    let json = serde_json::to_string(&vec![line_node]).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+    for (col, title) in ["杆塔编号", "经度", "纬度", "高度", "半径", "塔型", "资产编号"].iter().enumerate() {
+        sheet.write_string(0, col as u16, title, None).map_err(|e|anyhow!(e))?;
+    }
+    for (col, key) in keys.iter().enumerate() {
+        sheet.write_string(0, col as u16 + 7, key, None).map_err(|e|anyhow!(e))?;
+    }
 
-    Ok(json)
-}
\ No newline at end of file
+    for (idx, station) in stations.iter().enumerate() {
+        let row = idx as u32 + 1;
+        sheet.write_string(row, 0, station.name.as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 1, station.longitude.to_string().as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 2, station.latitude.to_string().as_str(), None).map_err(|e|anyhow!(e))?;
+        sheet.write_string(row, 3, station.height.to_string().as_str(), None).map_err(|e|anyhow!(e))?;
+        if let Some(radius) = station.radius {
+            sheet.write_number(row, 4, radius, None).map_err(|e|anyhow!(e))?;
+        }
+        if let Some(tower_type) = station.tower_type.as_ref() {
+            sheet.write_string(row, 5, tower_type.as_str(), None).map_err(|e|anyhow!(e))?;
+        }
+        if station.id != station.name && !station.id.is_empty() {
+            sheet.write_string(row, 6, station.id.as_str(), None).map_err(|e|anyhow!(e))?;
+        }
+        for (col, key) in keys.iter().enumerate() {
+            match station.attributes.get(*key) {
+                Some(AttributeValue::Number(v)) => sheet.write_number(row, col as u16 + 7, *v, None).map_err(|e|anyhow!(e))?,
+                Some(AttributeValue::Text(v)) => sheet.write_string(row, col as u16 + 7, v.as_str(), None).map_err(|e|anyhow!(e))?,
+                None => {}
+            }
+        }
+    }
+
+    workbook.close().map_err(|e|anyhow!(e))?;
+
+    Ok(())
+}
@@ -5,48 +5,30 @@ use std::{mem, path};
 use std::path::Path;
 use anyhow::anyhow;
 use tauri::InvokeError;
-use xlsxwriter::Workbook;
 use xml::EventReader;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
 use xml::reader::XmlEvent;
-use crate::station::{Station, STATION, TreeNode};
+use crate::station::Station;
+use crate::station::excel::write_station_excel;
+use crate::station::line::load_line;
+use crate::station::order::{sort_stations, STATION_ORDER};
 use crate::utils::{ensure_dir_exists, is_kml_file, file_name, new_invoke_err, to_invoke_err};
 
 #[tauri::command]
-pub fn kml_to_excel(kml_file: &str, output_dir: &str) -> Result<(), InvokeError> {
+pub async fn kml_to_excel(kml_file: &str, output_dir: &str) -> Result<(), InvokeError> {
 
     ensure_dir_exists(output_dir).map_err(to_invoke_err)?;
 
     if is_kml_file(kml_file) {
-        let data = kml_to_station_list(kml_file).map_err(to_invoke_err)?;
+        let mut data = kml_to_station_list(kml_file).map_err(to_invoke_err)?;
+        sort_stations(&mut data, *STATION_ORDER.lock().await);
 
         let line_name = file_name(kml_file).map_err(to_invoke_err)?;
         let file_name = format!("{}.xlsx",line_name);
 
         // 将数据保存到Excel文件
         let output_file = Path::new(output_dir).join(file_name);
-        let workbook = Workbook::new(output_file.as_os_str().to_str()
-            .unwrap())
-            .map_err(|e|anyhow!(e))
-            .map_err(to_invoke_err)?;
-        let mut sheet = workbook.add_worksheet(None).unwrap();
-        let mut row = 0;
-
-        sheet.write_string(0,0,"杆塔编号",None).unwrap();
-        sheet.write_string(0,1,"经度",None).unwrap();
-        sheet.write_string(0,2,"纬度",None).unwrap();
-        sheet.write_string(0,3,"高度",None).unwrap();
-
-        row += 1;
-
-        for station in data.into_iter() {
-            sheet.write_string(row,0,station.name.as_str(),None).unwrap();
-            sheet.write_string(row,1,station.longitude.to_string().as_str(),None).unwrap();
-            sheet.write_string(row,2,station.latitude.to_string().as_str(),None).unwrap();
-            sheet.write_string(row,3,station.height.to_string().as_str(),None).unwrap();
-            row += 1;
-        }
-
-        workbook.close().unwrap();
+        write_station_excel(&data, output_file.as_os_str().to_str().unwrap()).map_err(to_invoke_err)?;
     } else {
         eprintln!("not kml file");
         return Err(new_invoke_err("not kml file"));
@@ -58,7 +40,7 @@ pub fn kml_to_excel(kml_file: &str, output_dir: &str) -> Result<(), InvokeError>
 }
 
 #[tauri::command]
-pub async fn kml_to_json(kml_file: &str) -> Result<String, InvokeError> {
+pub async fn kml_to_json(kml_file: &str, append: Option<bool>) -> Result<String, InvokeError> {
 
     if !is_kml_file(kml_file) {
         return Err(new_invoke_err("not kml file"));
@@ -66,25 +48,16 @@ pub async fn kml_to_json(kml_file: &str) -> Result<String, InvokeError> {
 
     let station_data = kml_to_station_list(kml_file).map_err(to_invoke_err)?;
 
-    *STATION.lock().await = station_data.clone();
-
-    let station_node: Vec<TreeNode> = station_data.into_iter().map(|v|v.into()).collect();
-
     let line_name = file_name(kml_file).map_err(to_invoke_err)?;
-    let line_node = TreeNode{
-        key: line_name.clone(),
-        label: line_name.clone(),
-        children: Some(station_node),
-    };
-    let json = serde_json::to_string(&vec![line_node]).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+    let json = load_line(line_name.as_str(), station_data, append.unwrap_or_default()).await.map_err(to_invoke_err)?;
 
     Ok(json)
 }
 
 
 
-fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
-    let file = File::open(kml_file).unwrap();
+pub fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
+    let file = File::open(kml_file)?;
     let file = BufReader::new(file);
     let mut parser = EventReader::new(file);
     let mut data: Vec<Station> = Vec::new();
@@ -94,6 +67,9 @@ fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
     let mut in_name = false;
     let mut in_point = false;
     let mut in_coordinates = false;
+    // ExtendedData 中 <Data name=".."><value> 或 <SimpleData name=".."> 的字段名
+    let mut data_name: Option<String> = None;
+    let mut in_value = false;
 
     for e in parser {
         match e {
@@ -106,6 +82,11 @@ fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
                     "name" if in_placemark => in_name = true,
                     "Point" if in_placemark => in_point = true,
                     "coordinates" if in_point => in_coordinates = true,
+                    "Data" | "SimpleData" if in_placemark => {
+                        data_name = attributes.iter().find(|v| v.name.local_name == "name").map(|v| v.value.clone());
+                        in_value = name.local_name == "SimpleData";
+                    }
+                    "value" if data_name.is_some() => in_value = true,
                     _ => {}
                 }
             }
@@ -117,6 +98,10 @@ fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
                     station.as_mut().unwrap().longitude = parts[0].parse().map_err(|e: std::num::ParseFloatError| anyhow!(e.clone()))?;
                     station.as_mut().unwrap().latitude = parts[1].parse().map_err(|e: std::num::ParseFloatError| anyhow!(e.clone()))?;
                     station.as_mut().unwrap().height = parts[2].parse().map_err(|e: std::num::ParseFloatError| anyhow!(e.clone())).map_err(to_invoke_err).unwrap_or_default();
+                } else if in_value {
+                    if let Some(key) = data_name.as_deref() {
+                        station.as_mut().unwrap().set_attribute(key, content.as_str());
+                    }
                 }
             }
             Ok(XmlEvent::EndElement { ref name }) => {
@@ -129,6 +114,11 @@ fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
                     "name" if in_placemark => in_name = false,
                     "Point" if in_placemark => in_point = false,
                     "coordinates" if in_point => in_coordinates = false,
+                    "Data" | "SimpleData" => {
+                        data_name = None;
+                        in_value = false;
+                    }
+                    "value" => in_value = false,
                     _ => {}
                 }
             }
@@ -141,4 +131,43 @@ fn kml_to_station_list(kml_file: &str) -> anyhow::Result<Vec<Station>> {
     }
 
     Ok(data)
-}
\ No newline at end of file
+}
+
+/// 将台账写为 KML, 半径、塔型写入 ExtendedData
+pub fn write_station_kml(stations: &[Station], line_name: &str, output_file: &str) -> anyhow::Result<()> {
+    let mut kml = String::new();
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    kml.push_str(&format!("<name>{}</name>\n", escape_str_pcdata(line_name)));
+
+    for station in stations.iter() {
+        kml.push_str("<Placemark>\n");
+        kml.push_str(&format!("<name>{}</name>\n", escape_str_pcdata(station.name.as_str())));
+        let mut data: Vec<(String, String)> = vec![];
+        if station.id != station.name && !station.id.is_empty() {
+            data.push(("asset_id".to_string(), station.id.clone()));
+        }
+        if let Some(radius) = station.radius {
+            data.push(("radius".to_string(), radius.to_string()));
+        }
+        if let Some(tower_type) = station.tower_type.as_ref() {
+            data.push(("type".to_string(), tower_type.clone()));
+        }
+        data.extend(station.attributes.iter().map(|(k, v)| (k.clone(), v.to_string())));
+
+        if !data.is_empty() {
+            kml.push_str("<ExtendedData>\n");
+            for (key, value) in data.iter() {
+                kml.push_str(&format!("<Data name=\"{}\"><value>{}</value></Data>\n", escape_str_attribute(key), escape_str_pcdata(value)));
+            }
+            kml.push_str("</ExtendedData>\n");
+        }
+        kml.push_str(&format!("<Point><coordinates>{},{},{}</coordinates></Point>\n", station.longitude, station.latitude, station.height));
+        kml.push_str("</Placemark>\n");
+    }
+
+    kml.push_str("</Document>\n</kml>\n");
+    std::fs::write(output_file, kml)?;
+
+    Ok(())
+}
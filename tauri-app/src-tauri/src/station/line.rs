@@ -0,0 +1,182 @@
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+use tauri::InvokeError;
+use crate::handle::clear_line_results;
+use crate::station::{Station, STATION, TreeNode};
+use crate::station::edit::{LedgerHistory, HISTORY};
+use crate::station::order::{sort_stations, StationOrder, STATION_ORDER};
+use crate::station::validate::{validate_stations, warnings_node, WarningKind};
+use crate::utils::{new_invoke_err, to_invoke_err};
+
+/// 已加载的线路, 按导入顺序排列
+pub static LINES: Lazy<Mutex<Vec<Line>>> = Lazy::new(|| {
+    Mutex::new(vec![])
+});
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Line {
+    /// 线路标识, 即台账文件名
+    pub id: String,
+    pub name: String,
+    /// 电压等级, 如 "220kV"
+    pub voltage: Option<String>,
+}
+
+impl Line {
+    pub fn new(id: &str) -> Self {
+        Line {
+            id: id.to_string(),
+            name: id.to_string(),
+            voltage: parse_voltage(id),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.voltage.as_ref() {
+            Some(voltage) if !self.name.contains(voltage.as_str()) => format!("{} {}", voltage, self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// 从线路名称中识别电压等级, 如 "220kV 某某线"、"500千伏某某线"
+pub fn parse_voltage(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    let idx = ["kv", "千伏"].iter().filter_map(|v| lower.find(v)).min()?;
+
+    let digits: String = lower[..idx].trim_end().chars().rev()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<Vec<char>>().into_iter().rev().collect();
+    if digits.is_empty() {
+        return None;
+    }
+
+    Some(format!("{}kV", digits))
+}
+
+/// 是否同时加载了多条线路, 多条线路时输出按线路分目录
+pub async fn multi_line() -> bool {
+    LINES.lock().await.len() > 1
+}
+
+/// 导入一条线路的台账, append 为 true 时与已加载的线路并存(同名线路会被替换), 否则替换全部台账
+///
+/// 返回所有已加载线路的树, 台账告警附在最后
+pub async fn load_line(line_id: &str, mut stations: Vec<Station>, append: bool) -> anyhow::Result<String> {
+    for station in stations.iter_mut() {
+        station.line = line_id.to_string();
+        if station.id.is_empty() {
+            station.id = station.name.clone();
+        }
+    }
+
+    // 资产编号用于区分杆塔, 重复时归属结果会互相覆盖
+    let warnings = validate_stations(&stations);
+    if let Some(warning) = warnings.iter().find(|v| v.kind == WarningKind::DuplicateId) {
+        return Err(anyhow::Error::msg(format!("duplicate asset id: {}", warning.message)));
+    }
+
+    // 被替换的线路的归属结果不再有效
+    clear_line_results(if append { Some(line_id) } else { None }).await;
+
+    let mut all = STATION.lock().await;
+    let mut lines = LINES.lock().await;
+    if !append {
+        all.clear();
+        lines.clear();
+    }
+
+    match all.iter().position(|v| v.line == line_id) {
+        Some(idx) => {
+            all.retain(|v| v.line != line_id);
+            all.splice(idx..idx, stations);
+        }
+        None => all.extend(stations),
+    }
+    if !lines.iter().any(|v| v.id == line_id) {
+        lines.push(Line::new(line_id));
+    }
+    // 撤销记录中保存的是整本台账, 重新导入后不能再撤销到导入前
+    *HISTORY.lock().await = LedgerHistory::default();
+
+    let warnings = validate_stations(&all);
+    let mut tree_node_list = line_tree(&all, &lines, *STATION_ORDER.lock().await);
+    // 台账告警作为单独的节点附在线路之后
+    tree_node_list.extend(warnings_node(&warnings));
+
+    let json = serde_json::to_string(&tree_node_list).map_err(|e|anyhow!(e))?;
+
+    Ok(json)
+}
+
+/// 每条线路一个节点, 杆塔按当前排序方式排列
+pub fn line_tree(stations: &[Station], lines: &[Line], order: StationOrder) -> Vec<TreeNode> {
+    lines.iter().map(|line| {
+        let mut station_data: Vec<Station> = stations.iter().filter(|v| v.line == line.id).cloned().collect();
+        sort_stations(&mut station_data, order);
+
+        TreeNode {
+            key: line.id.clone(),
+            label: line.label(),
+            children: Some(station_data.into_iter().map(|v|v.into()).collect()),
+        }
+    }).collect()
+}
+
+#[tauri::command]
+pub async fn line_list() -> Result<String, InvokeError> {
+    let lines = LINES.lock().await.clone();
+    let json = serde_json::to_string(&lines).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 修改线路名称和电压等级
+#[tauri::command]
+pub async fn set_line_info(id: &str, name: Option<String>, voltage: Option<String>) -> Result<(), InvokeError> {
+    let mut lines = LINES.lock().await;
+    let line = lines.iter_mut().find(|v| v.id == id).ok_or(new_invoke_err("line not found"))?;
+
+    if let Some(name) = name {
+        line.name = name;
+    }
+    if voltage.is_some() {
+        line.voltage = voltage;
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_line(id: &str) -> Result<(), InvokeError> {
+    clear_line_results(Some(id)).await;
+    STATION.lock().await.retain(|v| v.line != id);
+    LINES.lock().await.retain(|v| v.id != id);
+    *HISTORY.lock().await = LedgerHistory::default();
+
+    Ok(())
+}
+
+#[test]
+fn test_line() {
+    assert_eq!(parse_voltage("220kV 茶园线"), Some("220kV".to_string()));
+    assert_eq!(parse_voltage("500千伏东西线"), Some("500kV".to_string()));
+    assert_eq!(parse_voltage("茶园线"), None);
+    assert_eq!(Line::new("茶园线").label(), "茶园线");
+
+    let station = |line: &str, name: &str| Station { line: line.to_string(), ..crate::fixtures::station(name, 120.0) };
+    let stations = vec![station("A", "2"), station("B", "1"), station("A", "1")];
+    let lines = vec![Line::new("A"), Line::new("B")];
+
+    let tree = line_tree(&stations, &lines, StationOrder::Natural);
+    assert_eq!(tree.len(), 2);
+    let names: Vec<String> = tree[0].children.as_ref().unwrap().iter().map(|v| v.key.clone()).collect();
+    assert_eq!(names, vec!["A/1", "A/2"]);
+
+    // 编号重复且没有资产编号的两基塔无法区分, 拒绝导入
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let duplicated = vec![crate::fixtures::station("#1", 120.0), crate::fixtures::station("#1", 120.004)];
+    assert!(rt.block_on(load_line("A", duplicated, false)).is_err());
+}
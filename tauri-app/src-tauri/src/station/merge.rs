@@ -0,0 +1,372 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use crate::station::Station;
+use crate::station::excel::excel_to_station_list;
+use crate::station::kml::kml_to_station_list;
+use crate::station::line::load_line;
+use crate::station::order::TowerNumber;
+use crate::utils::{file_name, is_excel_file, is_kml_file, new_invoke_err, to_invoke_err};
+
+/// 位置相差小于该值(米)视为同一位置
+pub static SAME_POSITION: f64 = 0.5;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MergeSource {
+    /// 第一个台账, 通常为位置准确的 KML
+    #[default]
+    Left,
+    /// 第二个台账, 通常为名称、属性准确的 Excel
+    Right,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// 编号匹配不上时, 按距离匹配的最大距离(米)
+    pub match_distance: f64,
+    /// 坐标、高度取自哪个台账
+    pub position: MergeSource,
+    /// 杆塔编号取自哪个台账
+    pub name: MergeSource,
+    /// 资产编号、半径、塔型及其他属性取自哪个台账, 缺失时取另一个
+    pub attributes: MergeSource,
+    /// 合并结果中保留未匹配的杆塔
+    pub keep_unmatched: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            match_distance: 20.0,
+            position: MergeSource::Left,
+            name: MergeSource::Right,
+            attributes: MergeSource::Right,
+            keep_unmatched: true,
+        }
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MatchMethod {
+    /// 编号完全相同
+    #[default]
+    Name,
+    /// 规范化后的编号相同, 如 "1号" 与 "#001"
+    Number,
+    /// 按位置就近匹配
+    Distance,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct StationMatch {
+    pub left: String,
+    pub right: String,
+    pub method: MatchMethod,
+    pub distance: f64,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Conflict {
+    pub left: String,
+    pub right: String,
+    pub field: String,
+    pub left_value: String,
+    pub right_value: String,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct MergeResult {
+    pub stations: Vec<Station>,
+    pub matches: Vec<StationMatch>,
+    pub unmatched_left: Vec<String>,
+    pub unmatched_right: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct MovedStation {
+    pub name: String,
+    pub distance: f64,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct RenamedStation {
+    pub old_name: String,
+    pub new_name: String,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<RenamedStation>,
+    pub moved: Vec<MovedStation>,
+    /// 高度、半径、塔型及其他属性有变化的杆塔
+    pub changed: Vec<Conflict>,
+}
+
+/// 按扩展名读取 KML 或 Excel 台账
+pub fn read_station_list(file: &str) -> anyhow::Result<Vec<Station>> {
+    if is_kml_file(file) {
+        kml_to_station_list(file)
+    } else if is_excel_file(file) {
+        excel_to_station_list(file)
+    } else {
+        Err(anyhow::Error::msg("not kml or excel file"))
+    }
+}
+
+/// 合并同一线路的两个台账, apply 为 true 时以合并结果替换第一个台账对应的线路
+#[tauri::command]
+pub async fn merge_ledgers(left_file: &str, right_file: &str, options: Option<MergeOptions>, apply: Option<bool>) -> Result<String, InvokeError> {
+    let options = options.unwrap_or_default();
+
+    let left = read_station_list(left_file).map_err(to_invoke_err)?;
+    let right = read_station_list(right_file).map_err(to_invoke_err)?;
+    let result = merge_stations(&left, &right, &options);
+
+    if apply.unwrap_or_default() {
+        if result.stations.is_empty() {
+            return Err(new_invoke_err("merged ledger is empty"));
+        }
+        let line_name = file_name(left_file).map_err(to_invoke_err)?;
+        load_line(line_name.as_str(), result.stations.clone(), true).await.map_err(to_invoke_err)?;
+    }
+
+    let json = serde_json::to_string(&result).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 比较同一台账的两个版本
+#[tauri::command]
+pub async fn diff_ledgers(old_file: &str, new_file: &str, match_distance: Option<f64>) -> Result<String, InvokeError> {
+    let old = read_station_list(old_file).map_err(to_invoke_err)?;
+    let new = read_station_list(new_file).map_err(to_invoke_err)?;
+
+    let diff = diff_stations(&old, &new, match_distance.unwrap_or(MergeOptions::default().match_distance));
+
+    let json = serde_json::to_string(&diff).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+/// 先按编号, 再按规范化编号, 最后按距离一一匹配, 返回 (左下标, 右下标, 方式)
+pub fn match_stations(left: &[Station], right: &[Station], match_distance: f64) -> Vec<(usize, usize, MatchMethod)> {
+    let mut matched = vec![];
+    let mut left_used = vec![false; left.len()];
+    let mut right_used = vec![false; right.len()];
+
+    let numbers: Vec<TowerNumber> = right.iter().map(|v| TowerNumber::parse(v.name.as_str())).collect();
+
+    for method in [MatchMethod::Name, MatchMethod::Number] {
+        for (i, station) in left.iter().enumerate() {
+            if left_used[i] {
+                continue;
+            }
+            let number = TowerNumber::parse(station.name.as_str());
+            let found = (0..right.len()).find(|j| !right_used[*j] && match method {
+                MatchMethod::Name => right[*j].name == station.name,
+                _ => number.number.is_some() && numbers[*j] == number,
+            });
+            if let Some(j) = found {
+                left_used[i] = true;
+                right_used[j] = true;
+                matched.push((i, j, method));
+            }
+        }
+    }
+
+    // 剩余的按距离由近到远配对
+    let mut pairs: Vec<(usize, usize, f64)> = vec![];
+    for (i, a) in left.iter().enumerate().filter(|(i, _)| !left_used[*i]) {
+        for (j, b) in right.iter().enumerate().filter(|(j, _)| !right_used[*j]) {
+            let distance = a.distance_to(b);
+            if distance <= match_distance {
+                pairs.push((i, j, distance));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+    for (i, j, _) in pairs.into_iter() {
+        if !left_used[i] && !right_used[j] {
+            left_used[i] = true;
+            right_used[j] = true;
+            matched.push((i, j, MatchMethod::Distance));
+        }
+    }
+
+    matched.sort_by_key(|v| v.0);
+    matched
+}
+
+fn format_option<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// 两基杆塔高度、半径、塔型及两者都有的属性的差异
+fn attribute_conflicts(a: &Station, b: &Station) -> Vec<Conflict> {
+    let conflict = |field: &str, left_value: String, right_value: String| Conflict {
+        left: a.name.clone(),
+        right: b.name.clone(),
+        field: field.to_string(),
+        left_value,
+        right_value,
+    };
+
+    let mut conflicts = vec![];
+    if a.height != b.height && a.height != 0.0 && b.height != 0.0 {
+        conflicts.push(conflict("height", a.height.to_string(), b.height.to_string()));
+    }
+    if a.radius.is_some() && b.radius.is_some() && a.radius != b.radius {
+        conflicts.push(conflict("radius", format_option(&a.radius), format_option(&b.radius)));
+    }
+    if a.tower_type.is_some() && b.tower_type.is_some() && a.tower_type != b.tower_type {
+        conflicts.push(conflict("tower_type", format_option(&a.tower_type), format_option(&b.tower_type)));
+    }
+    for (key, value) in a.attributes.iter() {
+        if let Some(other) = b.attributes.get(key).filter(|v| *v != value) {
+            conflicts.push(conflict(key, value.to_string(), other.to_string()));
+        }
+    }
+
+    conflicts
+}
+
+pub fn merge_stations(left: &[Station], right: &[Station], options: &MergeOptions) -> MergeResult {
+    let matched = match_stations(left, right, options.match_distance);
+    let mut result = MergeResult::default();
+
+    let pick = |source: MergeSource, a: &Station, b: &Station| -> Station {
+        match source {
+            MergeSource::Left => a.clone(),
+            MergeSource::Right => b.clone(),
+        }
+    };
+
+    for (i, station) in left.iter().enumerate() {
+        let (j, method) = match matched.iter().find(|v| v.0 == i) {
+            Some(v) => (v.1, v.2),
+            None => {
+                result.unmatched_left.push(station.name.clone());
+                if options.keep_unmatched {
+                    result.stations.push(station.clone());
+                }
+                continue;
+            }
+        };
+        let other = &right[j];
+        let distance = station.distance_to(other);
+
+        result.matches.push(StationMatch { left: station.name.clone(), right: other.name.clone(), method, distance });
+
+        if method != MatchMethod::Distance && distance > options.match_distance {
+            result.conflicts.push(Conflict {
+                left: station.name.clone(),
+                right: other.name.clone(),
+                field: "position".to_string(),
+                left_value: format!("{},{}", station.longitude, station.latitude),
+                right_value: format!("{},{}", other.longitude, other.latitude),
+            });
+        }
+        result.conflicts.extend(attribute_conflicts(station, other));
+
+        let position = pick(options.position, station, other);
+        let name = pick(options.name, station, other);
+        let attributes = pick(options.attributes, station, other);
+        let fallback = pick(options.attributes, other, station);
+
+        let mut merged_attributes = fallback.attributes.clone();
+        merged_attributes.extend(attributes.attributes.clone());
+
+        result.stations.push(Station {
+            line: station.line.clone(),
+            id: if attributes.id.is_empty() { fallback.id.clone() } else { attributes.id.clone() },
+            name: name.name,
+            longitude: position.longitude,
+            latitude: position.latitude,
+            height: position.height,
+            radius: attributes.radius.or(fallback.radius),
+            tower_type: attributes.tower_type.or(fallback.tower_type),
+            attributes: merged_attributes,
+        });
+    }
+
+    for (j, station) in right.iter().enumerate() {
+        if !matched.iter().any(|v| v.1 == j) {
+            result.unmatched_right.push(station.name.clone());
+            if options.keep_unmatched {
+                result.stations.push(station.clone());
+            }
+        }
+    }
+
+    result
+}
+
+pub fn diff_stations(old: &[Station], new: &[Station], match_distance: f64) -> LedgerDiff {
+    let matched = match_stations(old, new, match_distance);
+    let mut diff = LedgerDiff::default();
+
+    for (i, j, _) in matched.iter() {
+        let (a, b) = (&old[*i], &new[*j]);
+        if a.name != b.name {
+            diff.renamed.push(RenamedStation { old_name: a.name.clone(), new_name: b.name.clone() });
+        }
+
+        let distance = a.distance_to(b);
+        if distance > SAME_POSITION {
+            diff.moved.push(MovedStation { name: b.name.clone(), distance });
+        }
+
+        let mut changed = attribute_conflicts(a, b);
+        // 版本比较中新增或删除属性也算变化
+        if a.radius.is_some() != b.radius.is_some() {
+            changed.push(Conflict { left: a.name.clone(), right: b.name.clone(), field: "radius".to_string(), left_value: format_option(&a.radius), right_value: format_option(&b.radius) });
+        }
+        if a.tower_type.is_some() != b.tower_type.is_some() {
+            changed.push(Conflict { left: a.name.clone(), right: b.name.clone(), field: "tower_type".to_string(), left_value: format_option(&a.tower_type), right_value: format_option(&b.tower_type) });
+        }
+        diff.changed.extend(changed);
+    }
+
+    diff.removed = old.iter().enumerate().filter(|(i, _)| !matched.iter().any(|v| v.0 == *i)).map(|(_, v)| v.name.clone()).collect();
+    diff.added = new.iter().enumerate().filter(|(j, _)| !matched.iter().any(|v| v.1 == *j)).map(|(_, v)| v.name.clone()).collect();
+
+    diff
+}
+
+#[test]
+fn test_merge_stations() {
+    use crate::fixtures::station;
+
+    // KML 位置准确但编号不规范, Excel 编号规范并带塔型
+    let kml = vec![station("1号", 120.0), station("2号", 120.004), station("未命名", 120.008), station("5号", 120.02)];
+    let mut excel = vec![station("#001", 120.00001), station("#002", 120.0041), station("#003", 120.00805), station("#004", 120.012)];
+    excel[1].tower_type = Some("耐张塔".to_string());
+
+    let result = merge_stations(&kml, &excel, &MergeOptions::default());
+
+    let methods: Vec<MatchMethod> = result.matches.iter().map(|v| v.method).collect();
+    assert_eq!(methods, vec![MatchMethod::Number, MatchMethod::Number, MatchMethod::Distance]);
+    assert_eq!(result.unmatched_left, vec!["5号"]);
+    assert_eq!(result.unmatched_right, vec!["#004"]);
+    assert!(result.conflicts.is_empty());
+
+    assert_eq!(result.stations[1].name, "#002");
+    assert_eq!(result.stations[1].longitude, 120.004);
+    assert_eq!(result.stations[1].tower_type.as_deref(), Some("耐张塔"));
+
+    let mut new = kml.clone();
+    new[0].longitude = 120.0001;
+    new[2].name = "3号".to_string();
+    new.remove(3);
+    new.push(station("4号", 120.012));
+
+    let diff = diff_stations(&kml, &new, 20.0);
+    assert_eq!(diff.moved.len(), 1);
+    assert_eq!(diff.renamed[0].new_name, "3号");
+    assert_eq!(diff.removed, vec!["5号"]);
+    assert_eq!(diff.added, vec!["4号"]);
+}
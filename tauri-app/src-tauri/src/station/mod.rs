@@ -1,12 +1,21 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
 use tauri::async_runtime::Mutex;
 use crate::utils::file_name;
+use crate::handle::plane_distance;
 
 pub mod kml;
 pub mod excel;
+pub mod order;
+pub mod validate;
+pub mod route;
+pub mod edit;
+pub mod line;
+pub mod merge;
 
 pub static STATION: Lazy<Mutex<Vec<Station>>> = Lazy::new(|| {
     Mutex::new(vec![])
@@ -14,19 +23,59 @@ pub static STATION: Lazy<Mutex<Vec<Station>>> = Lazy::new(|| {
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct Station {
+    /// 所属线路, 即导入的台账文件名
+    #[serde(default)]
+    pub line: String,
+    /// 资产编号, 台账中没有时导入时取杆塔编号, 之后改名、移动都不变
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub longitude: f64,
     pub latitude: f64,
     pub height: f64,
+    /// 台账中指定的归属半径(米)
+    pub radius: Option<f64>,
+    /// 塔型, 用于按塔型指定半径
+    pub tower_type: Option<String>,
+    /// 台账中的其他属性, 如电压等级、材质、投运年份
+    #[serde(default)]
+    pub attributes: BTreeMap<String, AttributeValue>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AttributeValue {
+    Number(f64),
+    Text(String),
+}
+
+impl AttributeValue {
+    /// 能解析为数字的按数字保存
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        match value.parse::<f64>() {
+            Ok(v) if v.is_finite() => AttributeValue::Number(v),
+            _ => AttributeValue::Text(value.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeValue::Number(v) => write!(f, "{}", v),
+            AttributeValue::Text(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// 台账中表示资产编号的字段名
+pub static ASSET_ID_KEYS: [&str; 5] = ["asset_id", "id", "资产编号", "设备编码", "杆塔ID"];
+
+/// 杆塔以线路和资产编号区分, 移动、修改属性后仍视为同一基杆塔
 impl PartialEq for Station {
     fn eq(&self, other: &Self) -> bool {
-        if self.height == other.height && self.latitude == other.latitude && self.longitude == other.longitude && self.name == other.name {
-            true
-        } else {
-            false
-        }
+        self.line == other.line && self.identity() == other.identity()
     }
 }
 
@@ -34,13 +83,122 @@ impl Eq for Station {}
 
 impl Hash for Station {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
-        self.longitude.to_string().hash(state);
-        self.latitude.to_string().hash(state);
-        self.height.to_string().hash(state);
+        self.line.hash(state);
+        self.identity().hash(state);
     }
 }
 
+impl Station {
+    /// 到另一基杆塔的平面距离(米)
+    pub fn distance_to(&self, other: &Station) -> f64 {
+        plane_distance(self.longitude, self.latitude, other.longitude, other.latitude)
+    }
+
+    /// 没有资产编号时以杆塔编号区分
+    pub fn identity(&self) -> &str {
+        if self.id.is_empty() {
+            self.name.as_str()
+        } else {
+            self.id.as_str()
+        }
+    }
+
+    /// 所有字段都相同, 用于判断台账是否有修改
+    pub fn same_as(&self, other: &Station) -> bool {
+        self == other && self.name == other.name
+            && self.longitude == other.longitude && self.latitude == other.latitude && self.height == other.height
+            && self.radius == other.radius && self.tower_type == other.tower_type && self.attributes == other.attributes
+    }
+
+    /// 从台账字段中设置属性, 半径、塔型和资产编号写入对应字段, 其余保存到 attributes
+    pub fn set_attribute(&mut self, key: &str, value: &str) {
+        let value = value.trim();
+        match key {
+            "radius" | "半径" => self.radius = value.parse().ok(),
+            "type" | "tower_type" | "塔型" => self.tower_type = Some(value.to_string()).filter(|v| !v.is_empty()),
+            _ if ASSET_ID_KEYS.contains(&key) => self.id = value.to_string(),
+            _ if !value.is_empty() => {
+                self.attributes.insert(key.to_string(), AttributeValue::parse(value));
+            }
+            _ => {}
+        }
+    }
+
+    /// 按字段名取值, 用于输出目录模板和筛选, 支持 name、line、id、type、radius、height 及 attributes 中的字段
+    pub fn attribute(&self, key: &str) -> Option<String> {
+        match key {
+            "name" => Some(self.name.clone()),
+            "line" => Some(self.line.clone()),
+            "id" => Some(self.identity().to_string()),
+            "type" | "tower_type" => self.tower_type.clone(),
+            "radius" => self.radius.map(|v| v.to_string()),
+            "height" => Some(self.height.to_string()),
+            _ => self.attributes.get(key).map(|v| v.to_string()),
+        }
+    }
+
+    /// 按模板生成文本, 如 "{line}/{voltage}/{name}", 缺失的字段替换为空
+    pub fn render(&self, template: &str) -> String {
+        self.render_with(template, |v| v)
+    }
+
+    /// 按模板生成相对路径, 字段值中的路径分隔符替换为 "_", 跳过空目录和 "."、".."
+    pub fn render_path(&self, template: &str) -> PathBuf {
+        self.render_with(template, |v| v.replace(['/', '\\'], "_"))
+            .split(['/', '\\'])
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty() && *v != "." && *v != "..")
+            .map(path_segment)
+            .collect()
+    }
+
+    fn render_with(&self, template: &str, value: impl Fn(String) -> String) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            result.push_str(&rest[..start]);
+            result.push_str(value(self.attribute(&rest[start + 1..end]).unwrap_or_default()).as_str());
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+
+        result
+    }
+
+    /// 多条线路中唯一的标识, 如 "线路A/#1"
+    pub fn key(&self) -> String {
+        if self.line.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}/{}", self.line, self.name)
+        }
+    }
+}
+
+/// 用作单级目录名, 替换路径分隔符和文件名中不允许的字符, 空名称和 "."、".." 替换为 "_"
+pub fn path_segment(text: &str) -> String {
+    let text: String = text.trim().chars()
+        .map(|c| if c.is_control() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+
+    match text.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => text,
+    }
+}
+
+/// 按顺序相邻的杆塔之间的档距(米), 返回档的起始杆塔下标和档距, 不同线路之间不成档
+pub fn span_lengths(stations: &[Station]) -> Vec<(usize, f64)> {
+    stations.windows(2).enumerate()
+        .filter(|(_, v)| v[0].line == v[1].line)
+        .map(|(idx, v)| (idx, v[0].distance_to(&v[1])))
+        .collect()
+}
+
 #[derive(Default, Debug, Serialize, Deserialize, Clone)]
 pub struct TreeNode {
     pub key: String,
@@ -50,14 +208,58 @@ pub struct TreeNode {
 
 impl From<Station> for TreeNode {
     fn from(station: Station) -> Self {
+        let mut children = vec![
+            TreeNode { key: "longitude".to_string(), label: format!("经度: {}",station.longitude), children: None },
+            TreeNode { key: "latitude".to_string(), label: format!("纬度: {}", station.latitude), children: None },
+            TreeNode { key: "height".to_string(), label: format!("高度: {}", station.height), children: None },
+        ];
+        if let Some(radius) = station.radius {
+            children.push(TreeNode { key: "radius".to_string(), label: format!("半径: {}", radius), children: None });
+        }
+        if let Some(tower_type) = station.tower_type.as_ref() {
+            children.push(TreeNode { key: "tower_type".to_string(), label: format!("塔型: {}", tower_type), children: None });
+        }
+        if !station.id.is_empty() && station.id != station.name {
+            children.push(TreeNode { key: "id".to_string(), label: format!("资产编号: {}", station.id), children: None });
+        }
+        for (key, value) in station.attributes.iter() {
+            children.push(TreeNode { key: key.clone(), label: format!("{}: {}", key, value), children: None });
+        }
+
         TreeNode {
-            key: station.name.clone(),
+            key: station.key(),
             label: station.name.clone(),
-            children: Some(vec![
-                TreeNode { key: "longitude".to_string(), label: format!("经度: {}",station.longitude), children: None },
-                TreeNode { key: "latitude".to_string(), label: format!("纬度: {}", station.latitude), children: None },
-                TreeNode { key: "height".to_string(), label: format!("高度: {}", station.height), children: None },
-            ]),
+            children: Some(children),
         }
     }
 }
+
+#[test]
+fn test_station_attributes() {
+    let mut station = Station { line: "茶园线".to_string(), name: "#12".to_string(), ..Default::default() };
+    station.set_attribute("资产编号", "T0012");
+    station.set_attribute("塔型", "ZM1");
+    station.set_attribute("voltage", "220");
+    station.set_attribute("材质", "角钢");
+
+    assert_eq!(station.identity(), "T0012");
+    assert_eq!(station.tower_type.as_deref(), Some("ZM1"));
+    assert_eq!(station.attributes.get("voltage"), Some(&AttributeValue::Number(220.0)));
+    assert_eq!(station.render("{voltage}kV/{材质}/{name}{missing}"), "220kV/角钢/#12");
+
+    // 字段值不能跳出输出目录或增加目录层级
+    station.set_attribute("材质", "../角钢/钢管");
+    assert_eq!(station.render_path("../{材质}/{missing}/{name}"), PathBuf::from(".._角钢_钢管").join("#12"));
+    assert_eq!(path_segment(".."), "_");
+
+    // 改名、移动后仍是同一基杆塔
+    let mut moved = station.clone();
+    moved.name = "#12+1".to_string();
+    moved.longitude = 120.0;
+    assert_eq!(moved, station);
+    assert!(!moved.same_as(&station));
+
+    let mut set = std::collections::HashSet::new();
+    set.insert(station);
+    assert!(set.contains(&moved));
+}
@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+use tauri::InvokeError;
+use crate::station::{STATION, Station};
+
+pub static STATION_ORDER: Lazy<Mutex<StationOrder>> = Lazy::new(|| {
+    Mutex::new(StationOrder::default())
+});
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum StationOrder {
+    /// 按台账中的顺序
+    #[default]
+    Ledger,
+    /// 按杆塔编号自然排序
+    Natural,
+}
+
+/// 杆塔编号, 如 "1号", "#001", "N12+1", "J3", "12A", "支线5"
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct TowerNumber {
+    /// 编号前的线路/支线名称, 不含 "#"、"No." 以及 N/Z/J 等塔型字母
+    pub prefix: String,
+    pub number: Option<u64>,
+    /// "+1"、"-2" 之类的插入塔序号
+    pub sub: Vec<u64>,
+    /// "A"、"甲" 之类的分支后缀
+    pub suffix: String,
+}
+
+impl TowerNumber {
+    pub fn parse(name: &str) -> Self {
+        let name: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+        let name = name.trim_end_matches(['号', '塔', '杆']);
+
+        let digit_start = match name.find(|c: char| c.is_ascii_digit()) {
+            Some(idx) => idx,
+            None => return TowerNumber { prefix: name.to_string(), ..Default::default() },
+        };
+
+        let prefix = name[..digit_start].replace(['#', '＃'], "").replace("No.", "").replace("NO.", "");
+        let letters = prefix.chars().rev().take_while(|c| c.is_ascii_alphabetic()).count();
+        let prefix = if letters <= 2 {
+            prefix.chars().take(prefix.chars().count() - letters).collect()
+        } else {
+            prefix
+        };
+
+        let mut rest = &name[digit_start..];
+        let mut numbers = vec![];
+        loop {
+            let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            if end == 0 {
+                break;
+            }
+            numbers.push(rest[..end].parse().unwrap_or(u64::MAX));
+            rest = &rest[end..];
+
+            match rest.chars().next() {
+                Some(c @ ('+' | '-' | '_' | '.')) if rest[c.len_utf8()..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    rest = &rest[c.len_utf8()..];
+                }
+                _ => break,
+            }
+        }
+
+        TowerNumber {
+            prefix,
+            number: numbers.first().cloned(),
+            sub: numbers.into_iter().skip(1).collect(),
+            suffix: rest.to_string(),
+        }
+    }
+}
+
+impl PartialOrd for TowerNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TowerNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.prefix.cmp(&other.prefix)
+            .then_with(|| match (self.number, other.number) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| self.sub.cmp(&other.sub))
+            .then_with(|| self.suffix.cmp(&other.suffix))
+    }
+}
+
+/// 自然排序只在线路内进行, 线路之间保持导入顺序
+pub fn sort_stations(stations: &mut [Station], order: StationOrder) {
+    if order == StationOrder::Natural {
+        let mut lines: Vec<String> = vec![];
+        for station in stations.iter() {
+            if !lines.contains(&station.line) {
+                lines.push(station.line.clone());
+            }
+        }
+
+        stations.sort_by_cached_key(|v| (lines.iter().position(|line| *line == v.line), TowerNumber::parse(v.name.as_str())));
+    }
+}
+
+/// 按当前排序方式返回台账中的杆塔
+pub async fn ordered_stations() -> Vec<Station> {
+    let mut stations = STATION.lock().await.clone();
+    sort_stations(&mut stations, *STATION_ORDER.lock().await);
+
+    stations
+}
+
+#[tauri::command]
+pub async fn set_station_order(order: StationOrder) -> Result<(), InvokeError> {
+    *STATION_ORDER.lock().await = order;
+
+    Ok(())
+}
+
+#[test]
+fn test_tower_number() {
+    assert_eq!(TowerNumber::parse("1号").number, Some(1));
+    assert_eq!(TowerNumber::parse("#001").number, Some(1));
+    assert_eq!(TowerNumber::parse("N12+1"), TowerNumber { prefix: "".to_string(), number: Some(12), sub: vec![1], suffix: "".to_string() });
+    assert_eq!(TowerNumber::parse("福丰I线#12A").prefix, "福丰I线");
+    assert_eq!(TowerNumber::parse("福丰I线#12A").suffix, "A");
+
+    let mut stations: Vec<Station> = ["#10", "N12+1", "#2", "N12", "J3", "#1", "12A", "11号"].iter()
+        .map(|v| Station { name: v.to_string(), ..Default::default() })
+        .collect();
+    sort_stations(&mut stations, StationOrder::Natural);
+    let names: Vec<_> = stations.iter().map(|v| v.name.as_str()).collect();
+
+    assert_eq!(names, vec!["#1", "#2", "J3", "#10", "11号", "N12", "12A", "N12+1"]);
+}
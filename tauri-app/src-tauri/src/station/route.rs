@@ -0,0 +1,216 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tauri::InvokeError;
+use crate::station::{Station, STATION};
+use crate::station::edit::{edit_station, LedgerEdit};
+use crate::station::order::TowerNumber;
+use crate::utils::to_invoke_err;
+
+/// 相邻两塔距离大于中位档距的该倍数时视为跳到了另一条分支
+pub static BRANCH_FACTOR: f64 = 3.0;
+
+/// 2-opt 最多迭代轮数
+pub static MAX_ROUNDS: usize = 100;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Branch {
+    /// 分支起点杆塔
+    pub start: String,
+    /// 主线上离分支起点最近的杆塔(T 接点)
+    pub junction: String,
+    pub distance: f64,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct RouteResult {
+    /// 按几何位置重建的杆塔顺序
+    pub order: Vec<String>,
+    /// 线路总长(米)
+    pub length: f64,
+    pub branches: Vec<Branch>,
+    /// 编号与几何顺序不符, 可能编号错误的杆塔
+    pub mislabelled: Vec<String>,
+}
+
+/// 按几何位置重建杆塔顺序, apply 为 true 时用重建结果替换台账顺序, 与手动修改台账相同, 可以撤销
+///
+/// 每条线路单独重建, 不指定 line 时重建第一条线路
+#[tauri::command]
+pub async fn reconstruct_order(line: Option<String>, apply: Option<bool>) -> Result<String, InvokeError> {
+    let all = STATION.lock().await.clone();
+
+    let line = line.or(all.first().map(|v| v.line.clone())).unwrap_or_default();
+    let stations: Vec<Station> = all.iter().filter(|v| v.line == line).cloned().collect();
+
+    let route = route_order(&stations);
+    let result = route_result(&stations, &route);
+
+    if apply.unwrap_or_default() {
+        edit_station(LedgerEdit::Reorder { order: result.order.clone() }, Some(line)).await?;
+    }
+
+    let json = serde_json::to_string(&result).map_err(|e|anyhow!(e)).map_err(to_invoke_err)?;
+
+    Ok(json)
+}
+
+fn path_length(stations: &[Station], route: &[usize]) -> f64 {
+    route.windows(2).map(|v| stations[v[0]].distance_to(&stations[v[1]])).sum()
+}
+
+/// 最近邻串联后用 2-opt 优化, 返回杆塔下标
+pub fn route_order(stations: &[Station]) -> Vec<usize> {
+    if stations.len() < 3 {
+        return (0..stations.len()).collect();
+    }
+
+    // 从离中心最远的杆塔出发, 通常是线路的一端
+    let center = Station {
+        longitude: stations.iter().map(|v| v.longitude).sum::<f64>() / stations.len() as f64,
+        latitude: stations.iter().map(|v| v.latitude).sum::<f64>() / stations.len() as f64,
+        ..Default::default()
+    };
+    let start = (0..stations.len())
+        .max_by(|a, b| stations[*a].distance_to(&center).total_cmp(&stations[*b].distance_to(&center)))
+        .unwrap_or_default();
+
+    let mut route = vec![start];
+    let mut visited = vec![false; stations.len()];
+    visited[start] = true;
+    while route.len() < stations.len() {
+        let last = &stations[route[route.len() - 1]];
+        let next = (0..stations.len())
+            .filter(|v| !visited[*v])
+            .min_by(|a, b| last.distance_to(&stations[*a]).total_cmp(&last.distance_to(&stations[*b])))
+            .unwrap_or_default();
+        visited[next] = true;
+        route.push(next);
+    }
+
+    two_opt(stations, &mut route);
+
+    // 编号从小到大的方向
+    let first = TowerNumber::parse(stations[route[0]].name.as_str());
+    let last = TowerNumber::parse(stations[route[route.len() - 1]].name.as_str());
+    if first > last {
+        route.reverse();
+    }
+
+    route
+}
+
+/// 开放路径的 2-opt, 反转 route[i..=j] 使总长变短
+fn two_opt(stations: &[Station], route: &mut [usize]) {
+    let dist = |a: usize, b: usize| stations[a].distance_to(&stations[b]);
+
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for i in 0..route.len() - 1 {
+            for j in i + 1..route.len() {
+                let before = if i > 0 { dist(route[i - 1], route[i]) } else { 0.0 };
+                let after = if j + 1 < route.len() { dist(route[j], route[j + 1]) } else { 0.0 };
+                let new_before = if i > 0 { dist(route[i - 1], route[j]) } else { 0.0 };
+                let new_after = if j + 1 < route.len() { dist(route[i], route[j + 1]) } else { 0.0 };
+
+                if new_before + new_after < before + after - 1e-6 {
+                    route[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+pub fn route_result(stations: &[Station], route: &[usize]) -> RouteResult {
+    RouteResult {
+        order: route.iter().map(|v| stations[*v].name.clone()).collect(),
+        length: path_length(stations, route),
+        branches: find_branches(stations, route),
+        mislabelled: find_mislabelled(stations, route),
+    }
+}
+
+/// 路径中异常长的一档视为跳到分支, 分支起点挂在此前经过的最近杆塔上
+fn find_branches(stations: &[Station], route: &[usize]) -> Vec<Branch> {
+    let mut spans: Vec<f64> = route.windows(2).map(|v| stations[v[0]].distance_to(&stations[v[1]])).collect();
+    let jumps = spans.clone();
+    spans.sort_by(f64::total_cmp);
+    let median = match spans.get(spans.len() / 2) {
+        Some(median) if *median > 0.0 => *median,
+        _ => return vec![],
+    };
+
+    let mut branches = vec![];
+    for (idx, jump) in jumps.iter().enumerate() {
+        if *jump <= median * BRANCH_FACTOR {
+            continue;
+        }
+
+        let start = &stations[route[idx + 1]];
+        let junction = route[..=idx].iter()
+            .map(|v| (&stations[*v], stations[*v].distance_to(start)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((junction, distance)) = junction {
+            branches.push(Branch { start: start.name.clone(), junction: junction.name.clone(), distance });
+        }
+    }
+
+    branches
+}
+
+/// 前后两基编号递增且同属一条线路, 中间杆塔编号不在两者之间时视为编号错误
+fn find_mislabelled(stations: &[Station], route: &[usize]) -> Vec<String> {
+    let numbers: Vec<TowerNumber> = route.iter().map(|v| TowerNumber::parse(stations[*v].name.as_str())).collect();
+
+    let mut mislabelled = vec![];
+    for (idx, v) in numbers.windows(3).enumerate() {
+        let (prev, curr, next) = (&v[0], &v[1], &v[2]);
+        if prev.number.is_none() || curr.number.is_none() || next.number.is_none() {
+            continue;
+        }
+        if prev.prefix != curr.prefix || curr.prefix != next.prefix || prev >= next {
+            continue;
+        }
+
+        if curr <= prev || curr >= next {
+            mislabelled.push(stations[route[idx + 1]].name.clone());
+        }
+    }
+
+    mislabelled
+}
+
+#[test]
+fn test_route_order() {
+    use crate::fixtures::station_at;
+
+    // 1~6 号由西向东, 4 号塔编号错写成 9
+    let stations = vec![
+        station_at("5", 120.016, 30.0),
+        station_at("1", 120.0, 30.0),
+        station_at("3", 120.008, 30.0),
+        station_at("9", 120.012, 30.0001),
+        station_at("6", 120.020, 30.0),
+        station_at("2", 120.004, 30.0001),
+    ];
+
+    let route = route_order(&stations);
+    let result = route_result(&stations, &route);
+
+    assert_eq!(result.order, ["1", "2", "3", "9", "5", "6"]);
+    assert_eq!(result.mislabelled, vec!["9".to_string()]);
+    assert!(result.branches.is_empty());
+
+    // 支线 Z1、Z2 从 3 号塔向北 T 接
+    let mut stations: Vec<Station> = route.iter().map(|v| stations[*v].clone()).collect();
+    stations.push(station_at("Z1", 120.008, 30.0072));
+    stations.push(station_at("Z2", 120.008, 30.0108));
+
+    let branches = find_branches(&stations, &(0..stations.len()).collect::<Vec<usize>>());
+    assert_eq!(branches.len(), 1);
+    assert_eq!(branches[0].start, "Z1");
+    assert_eq!(branches[0].junction, "3");
+}
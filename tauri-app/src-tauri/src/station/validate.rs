@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use crate::station::{span_lengths, Station, TreeNode};
+use crate::station::order::TowerNumber;
+
+/// 两基杆塔距离小于该值(米)视为位置重复
+pub static DUPLICATE_DISTANCE: f64 = 1.0;
+
+/// 与线路中心距离超过该值(米)视为坐标异常
+pub static FAR_DISTANCE: f64 = 100_000.0;
+
+/// 档距大于中位数的该倍数视为异常长档
+pub static LONG_SPAN_FACTOR: f64 = 3.0;
+
+/// 档距小于中位数的该比例视为异常短档
+pub static SHORT_SPAN_FACTOR: f64 = 0.2;
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    #[default]
+    DuplicateName,
+    /// 同一线路内资产编号重复, 导入时拒绝
+    DuplicateId,
+    DuplicatePosition,
+    ZeroCoordinate,
+    SwappedAxes,
+    OutOfRange,
+    FarAway,
+    AbnormalSpan,
+    OrderBreak,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerWarning {
+    pub kind: WarningKind,
+    /// 杆塔的 Station::key()
+    pub station: String,
+    pub message: String,
+}
+
+impl LedgerWarning {
+    fn new(kind: WarningKind, station: &Station, message: String) -> Self {
+        LedgerWarning { kind, station: station.key(), message }
+    }
+}
+
+/// 检查台账(按台账顺序), 返回告警
+pub fn validate_stations(stations: &[Station]) -> Vec<LedgerWarning> {
+    let mut warnings = vec![];
+
+    check_coordinates(stations, &mut warnings);
+    check_duplicates(stations, &mut warnings);
+    check_far_away(stations, &mut warnings);
+    check_spans(stations, &mut warnings);
+    check_order(stations, &mut warnings);
+
+    warnings
+}
+
+/// 告警列表的树节点, 没有告警时返回 None
+pub fn warnings_node(warnings: &[LedgerWarning]) -> Option<TreeNode> {
+    if warnings.is_empty() {
+        return None;
+    }
+
+    let children = warnings.iter().enumerate().map(|(idx, v)| TreeNode {
+        key: format!("warning-{}", idx),
+        label: format!("{}: {}", v.station, v.message),
+        children: None,
+    }).collect();
+
+    Some(TreeNode {
+        key: "warnings".to_string(),
+        label: format!("台账告警({})", warnings.len()),
+        children: Some(children),
+    })
+}
+
+fn valid_coordinate(station: &Station) -> bool {
+    station.longitude != 0.0 && station.latitude != 0.0
+        && station.longitude.abs() <= 180.0 && station.latitude.abs() <= 90.0
+}
+
+fn check_coordinates(stations: &[Station], warnings: &mut Vec<LedgerWarning>) {
+    for station in stations.iter() {
+        let (lon, lat) = (station.longitude, station.latitude);
+
+        if lon == 0.0 || lat == 0.0 {
+            warnings.push(LedgerWarning::new(WarningKind::ZeroCoordinate, station, "经度或纬度为 0".to_string()));
+        } else if lat.abs() > 90.0 && lon.abs() <= 90.0 {
+            warnings.push(LedgerWarning::new(WarningKind::SwappedAxes, station, format!("纬度 {} 超出范围, 经纬度可能填反", lat)));
+        } else if lon.abs() > 180.0 || lat.abs() > 90.0 {
+            warnings.push(LedgerWarning::new(WarningKind::OutOfRange, station, format!("经纬度 ({}, {}) 超出范围", lon, lat)));
+        } else if (3.0..=54.0).contains(&lon) && (73.0..=136.0).contains(&lat) {
+            // 国内经度 73~136, 纬度 3~54
+            warnings.push(LedgerWarning::new(WarningKind::SwappedAxes, station, format!("经度 {} 纬度 {}, 经纬度可能填反", lon, lat)));
+        }
+    }
+}
+
+fn check_duplicates(stations: &[Station], warnings: &mut Vec<LedgerWarning>) {
+    for (idx, station) in stations.iter().enumerate() {
+        // 同塔多回的共用杆塔分属不同线路, 只在同一线路内检查重复
+        let same_line: Vec<&Station> = stations[..idx].iter().filter(|v| v.line == station.line).collect();
+        if same_line.iter().any(|v| v.name == station.name) {
+            warnings.push(LedgerWarning::new(WarningKind::DuplicateName, station, "杆塔编号重复".to_string()));
+        }
+        // 没有资产编号时以杆塔编号区分, 编号重复同样视为同一基杆塔
+        if let Some(same) = same_line.iter().find(|v| v.identity() == station.identity()) {
+            warnings.push(LedgerWarning::new(WarningKind::DuplicateId, station, format!("与 {} 资产编号 {} 重复", same.name, station.identity())));
+        }
+
+        if !valid_coordinate(station) {
+            continue;
+        }
+        let same = same_line.iter()
+            .find(|v| valid_coordinate(v) && v.distance_to(station) < DUPLICATE_DISTANCE);
+        if let Some(same) = same {
+            warnings.push(LedgerWarning::new(WarningKind::DuplicatePosition, station, format!("与 {} 位置重复", same.name)));
+        }
+    }
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    let mut values = values.to_vec();
+    values.sort_by(f64::total_cmp);
+
+    values.get(values.len() / 2).cloned()
+}
+
+fn check_far_away(stations: &[Station], warnings: &mut Vec<LedgerWarning>) {
+    let valid: Vec<&Station> = stations.iter().filter(|v| valid_coordinate(v)).collect();
+    let longitudes: Vec<f64> = valid.iter().map(|v| v.longitude).collect();
+    let latitudes: Vec<f64> = valid.iter().map(|v| v.latitude).collect();
+
+    let center = match (median(&longitudes), median(&latitudes)) {
+        (Some(longitude), Some(latitude)) => Station { longitude, latitude, ..Default::default() },
+        _ => return,
+    };
+
+    for station in valid.into_iter() {
+        let distance = station.distance_to(&center);
+        if distance > FAR_DISTANCE {
+            warnings.push(LedgerWarning::new(WarningKind::FarAway, station, format!("距线路中心 {:.0} 公里", distance / 1000.0)));
+        }
+    }
+}
+
+fn check_spans(stations: &[Station], warnings: &mut Vec<LedgerWarning>) {
+    let spans = span_lengths(stations);
+    let valid: Vec<f64> = spans.iter()
+        .filter(|(idx, _)| valid_coordinate(&stations[*idx]) && valid_coordinate(&stations[idx + 1]))
+        .map(|(_, v)| *v)
+        .collect();
+
+    let median = match median(&valid) {
+        Some(median) if median > 0.0 => median,
+        _ => return,
+    };
+
+    for (idx, span) in spans.iter() {
+        let (from, to) = (&stations[*idx], &stations[idx + 1]);
+        if !valid_coordinate(from) || !valid_coordinate(to) || *span < DUPLICATE_DISTANCE {
+            continue;
+        }
+
+        if *span > median * LONG_SPAN_FACTOR {
+            warnings.push(LedgerWarning::new(WarningKind::AbnormalSpan, to, format!("与 {} 档距 {:.0} 米, 远大于中位档距 {:.0} 米", from.name, span, median)));
+        } else if *span < median * SHORT_SPAN_FACTOR {
+            warnings.push(LedgerWarning::new(WarningKind::AbnormalSpan, to, format!("与 {} 档距 {:.0} 米, 远小于中位档距 {:.0} 米", from.name, span, median)));
+        }
+    }
+}
+
+fn check_order(stations: &[Station], warnings: &mut Vec<LedgerWarning>) {
+    let numbers: Vec<TowerNumber> = stations.iter().map(|v| TowerNumber::parse(v.name.as_str())).collect();
+
+    for (idx, pair) in numbers.windows(2).enumerate() {
+        let (prev, next) = (&pair[0], &pair[1]);
+        // 不同线路/支线之间不比较
+        if prev.prefix != next.prefix || stations[idx].line != stations[idx + 1].line {
+            continue;
+        }
+        let (a, b) = match (prev.number, next.number) {
+            (Some(a), Some(b)) => (a, b),
+            _ => continue,
+        };
+
+        let station = &stations[idx + 1];
+        if b < a {
+            warnings.push(LedgerWarning::new(WarningKind::OrderBreak, station, format!("编号小于上一基 {}", stations[idx].name)));
+        } else if b > a + 1 {
+            warnings.push(LedgerWarning::new(WarningKind::OrderBreak, station, format!("与上一基 {} 之间编号不连续", stations[idx].name)));
+        }
+    }
+}
+
+#[test]
+fn test_validate_stations() {
+    use crate::fixtures::station_at;
+
+    let stations = vec![
+        station_at("#1", 120.0, 30.0),
+        station_at("#2", 120.004, 30.0),
+        station_at("#2", 120.008, 30.0),
+        station_at("#3", 30.0, 120.012),
+        station_at("#5", 120.016, 30.0),
+        station_at("#4", 120.016, 30.0),
+        station_at("#7", 125.0, 30.0),
+        station_at("#8", 125.004, 30.0),
+        station_at("#9", 0.0, 30.0),
+        Station { id: "#9".to_string(), ..station_at("#10", 125.008, 30.0) },
+    ];
+
+    let warnings = validate_stations(&stations);
+    let kinds: Vec<(WarningKind, &str)> = warnings.iter().map(|v| (v.kind, v.station.as_str())).collect();
+
+    assert!(kinds.contains(&(WarningKind::DuplicateName, "#2")));
+    assert!(kinds.contains(&(WarningKind::DuplicateId, "#2")));
+    assert!(kinds.contains(&(WarningKind::SwappedAxes, "#3")));
+    assert!(kinds.contains(&(WarningKind::DuplicatePosition, "#4")));
+    assert!(kinds.contains(&(WarningKind::OrderBreak, "#5")));
+    assert!(kinds.contains(&(WarningKind::OrderBreak, "#4")));
+    assert!(kinds.contains(&(WarningKind::ZeroCoordinate, "#9")));
+    assert!(kinds.contains(&(WarningKind::DuplicateId, "#10")));
+    assert!(kinds.contains(&(WarningKind::AbnormalSpan, "#7")));
+    assert!(kinds.contains(&(WarningKind::FarAway, "#8")));
+    assert!(!kinds.iter().any(|v| v.1 == "#1"));
+
+    let node = warnings_node(&warnings).unwrap();
+    assert_eq!(node.children.unwrap().len(), warnings.len());
+}